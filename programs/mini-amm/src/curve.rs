@@ -0,0 +1,124 @@
+// Constant-product pool math, no accounts or CPI here
+pub const FEE_BPS: u128 = 30;
+const MAX_BPS: u128 = 10_000;
+
+// Floor of the square root, by Newton's method
+fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    let mut x = n;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+// Tokens taken and LP tokens minted for depositing `lamports`. The first deposit sets
+// the price with `max_tokens`, later ones take tokens at the pool ratio, rounded up.
+pub fn deposit(
+    sol_reserve: u64,
+    token_reserve: u64,
+    lp_supply: u64,
+    lamports: u64,
+    max_tokens: u64,
+) -> Option<(u64, u64)> {
+    let (tokens, minted) = if lp_supply == 0 {
+        let minted = isqrt((lamports as u128).checked_mul(max_tokens as u128)?);
+        (max_tokens as u128, minted)
+    } else {
+        let tokens = (lamports as u128)
+            .checked_mul(token_reserve as u128)?
+            .checked_add((sol_reserve as u128).checked_sub(1)?)?
+            .checked_div(sol_reserve as u128)?;
+        let minted = (lamports as u128).checked_mul(lp_supply as u128)? / sol_reserve as u128;
+        (tokens, minted)
+    };
+    if tokens > max_tokens as u128 || minted == 0 {
+        return None;
+    }
+    Some((tokens as u64, u64::try_from(minted).ok()?))
+}
+
+// Output of swapping `amount_in` into the pool, after the fee stays in the reserves
+pub fn swap_out(reserve_in: u64, reserve_out: u64, amount_in: u64) -> Option<u64> {
+    let in_after_fee = (amount_in as u128).checked_mul(MAX_BPS - FEE_BPS)? / MAX_BPS;
+    let out = in_after_fee.checked_mul(reserve_out as u128)?
+        / (reserve_in as u128).checked_add(in_after_fee)?;
+    u64::try_from(out).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn swap_never_lowers_k(
+            reserve_in in 1..u64::MAX / 2,
+            reserve_out in 1..u64::MAX / 2,
+            amount_in in 0..u64::MAX / 2,
+        ) {
+            let out = swap_out(reserve_in, reserve_out, amount_in).unwrap();
+            prop_assert!(out < reserve_out);
+            let k_before = reserve_in as u128 * reserve_out as u128;
+            let k_after = (reserve_in + amount_in) as u128 * (reserve_out - out) as u128;
+            prop_assert!(k_after >= k_before);
+        }
+
+        #[test]
+        fn deposit_never_dilutes_lp(
+            sol_reserve in 1..u64::MAX / 4,
+            token_reserve in 1..u64::MAX / 4,
+            lp_supply in 1..u64::MAX / 4,
+            lamports in 0..u64::MAX / 4,
+            max_tokens in any::<u64>(),
+        ) {
+            if let Some((tokens, minted)) =
+                deposit(sol_reserve, token_reserve, lp_supply, lamports, max_tokens)
+            {
+                prop_assert!(tokens <= max_tokens);
+                // the depositor's share of either reserve is not above what they put in
+                let share = |reserve: u64, added: u64| {
+                    minted as u128 * reserve as u128 <= added as u128 * lp_supply as u128
+                };
+                prop_assert!(share(sol_reserve, lamports));
+                prop_assert!(share(token_reserve, tokens));
+            }
+        }
+
+        #[test]
+        fn seeded_pool_pays_at_most_the_seed_price(
+            lamports in 1..u64::MAX / 4,
+            tokens in 1..u64::MAX / 4,
+            swaps in proptest::collection::vec(0..u64::MAX / 64, 1..8),
+        ) {
+            // the first deposit into an empty pool sets the price to lamports / tokens
+            let (taken, _) = deposit(0, 0, 0, lamports, tokens).unwrap();
+            let (mut sol_reserve, mut token_reserve) = (lamports, taken);
+            let (mut tokens_in, mut sol_out) = (0u128, 0u128);
+            for amount_in in swaps {
+                let out = swap_out(token_reserve, sol_reserve, amount_in).unwrap();
+                prop_assert!(out < sol_reserve);
+                token_reserve += amount_in;
+                sol_reserve -= out;
+                tokens_in += amount_in as u128;
+                sol_out += out as u128;
+            }
+            // a trader selling into the pool never gets more SOL than its tokens were worth at
+            // the seed price, so the seeded SOL can't be drained
+            prop_assert!(sol_out * tokens as u128 <= tokens_in * lamports as u128);
+        }
+
+        #[test]
+        fn first_deposit_mints_geometric_mean(lamports in any::<u64>(), tokens in any::<u64>()) {
+            if let Some((taken, minted)) = deposit(0, 0, 0, lamports, tokens) {
+                prop_assert_eq!(taken, tokens);
+                prop_assert_eq!(minted as u128, isqrt(lamports as u128 * tokens as u128));
+            }
+        }
+    }
+}
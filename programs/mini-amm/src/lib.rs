@@ -0,0 +1,324 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{transfer, Transfer};
+use anchor_spl::token::{self, Mint, Token, TokenAccount};
+
+pub mod curve;
+
+const POOL_SEED: &[u8] = b"pool";
+const TOKEN_VAULT_SEED: &[u8] = b"token_vault";
+const LP_MINT_SEED: &[u8] = b"lp_mint";
+
+declare_id!("9pGXXPJf3JxoEVcaGiUyKfEL5jC6BjBNZLcEssUoobVA");
+
+// Minimal constant-product SOL/token pool, one per token mint. The pool PDA holds the
+// SOL reserve in its own lamports, the token reserve sits in a vault it owns.
+#[program]
+mod mini_amm {
+    use super::*;
+
+    // Creates the pool of `token_mint` with its token vault and LP mint
+    pub fn initialize_pool(ctx: Context<InitializePool>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.token_mint = ctx.accounts.token_mint.key();
+        pool.lp_mint = ctx.accounts.lp_mint.key();
+        pool.bump = ctx.bumps.pool;
+
+        msg!("Pool created for {}", pool.token_mint);
+        Ok(())
+    }
+
+    // Deposits `lamports` and up to `max_tokens` at the pool ratio and mints LP tokens.
+    // The token authority may be a PDA of a calling program.
+    pub fn add_liquidity(ctx: Context<AddLiquidity>, lamports: u64, max_tokens: u64) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let (tokens, minted) = curve::deposit(
+            pool.sol_reserve,
+            pool.token_reserve,
+            ctx.accounts.lp_mint.supply,
+            lamports,
+            max_tokens,
+        )
+        .ok_or(AmmError::InvalidDeposit)?;
+
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.depositor.to_account_info(),
+                to: ctx.accounts.pool.to_account_info(),
+            },
+        );
+        transfer(cpi_context, lamports)?;
+
+        let cpi_context = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.depositor_token.to_account_info(),
+                to: ctx.accounts.token_vault.to_account_info(),
+                authority: ctx.accounts.token_authority.to_account_info(),
+            },
+        );
+        token::transfer(cpi_context, tokens)?;
+
+        let token_mint = ctx.accounts.pool.token_mint;
+        let seeds = &[POOL_SEED, token_mint.as_ref(), &[ctx.accounts.pool.bump]];
+        let signer = [&seeds[..]];
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::MintTo {
+                mint: ctx.accounts.lp_mint.to_account_info(),
+                to: ctx.accounts.lp_recipient.to_account_info(),
+                authority: ctx.accounts.pool.to_account_info(),
+            },
+            &signer,
+        );
+        token::mint_to(cpi_context, minted)?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.sol_reserve = pool
+            .sol_reserve
+            .checked_add(lamports)
+            .ok_or(AmmError::MathOverflow)?;
+        pool.token_reserve = pool
+            .token_reserve
+            .checked_add(tokens)
+            .ok_or(AmmError::MathOverflow)?;
+
+        msg!(
+            "Added {} lamports and {} tokens for {} LP",
+            lamports,
+            tokens,
+            minted
+        );
+        Ok(())
+    }
+
+    // Swaps `amount_in` lamports for tokens, or tokens for lamports, at the curve price
+    pub fn swap(ctx: Context<Swap>, amount_in: u64, min_out: u64, sol_in: bool) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let (reserve_in, reserve_out) = if sol_in {
+            (pool.sol_reserve, pool.token_reserve)
+        } else {
+            (pool.token_reserve, pool.sol_reserve)
+        };
+        let amount_out =
+            curve::swap_out(reserve_in, reserve_out, amount_in).ok_or(AmmError::MathOverflow)?;
+        if amount_out == 0 || amount_out < min_out {
+            return Err(AmmError::SlippageExceeded.into());
+        }
+
+        if sol_in {
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.trader.to_account_info(),
+                    to: ctx.accounts.pool.to_account_info(),
+                },
+            );
+            transfer(cpi_context, amount_in)?;
+
+            let token_mint = ctx.accounts.pool.token_mint;
+            let seeds = &[POOL_SEED, token_mint.as_ref(), &[ctx.accounts.pool.bump]];
+            let signer = [&seeds[..]];
+            let cpi_context = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.token_vault.to_account_info(),
+                    to: ctx.accounts.trader_token.to_account_info(),
+                    authority: ctx.accounts.pool.to_account_info(),
+                },
+                &signer,
+            );
+            token::transfer(cpi_context, amount_out)?;
+        } else {
+            let cpi_context = CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.trader_token.to_account_info(),
+                    to: ctx.accounts.token_vault.to_account_info(),
+                    authority: ctx.accounts.trader.to_account_info(),
+                },
+            );
+            token::transfer(cpi_context, amount_in)?;
+
+            // The pool PDA is owned by this program, so its lamports are moved directly
+            **ctx
+                .accounts
+                .pool
+                .to_account_info()
+                .try_borrow_mut_lamports()? -= amount_out;
+            **ctx.accounts.trader.try_borrow_mut_lamports()? += amount_out;
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        let (sol_reserve, token_reserve) = if sol_in {
+            (
+                pool.sol_reserve.checked_add(amount_in),
+                pool.token_reserve.checked_sub(amount_out),
+            )
+        } else {
+            (
+                pool.sol_reserve.checked_sub(amount_out),
+                pool.token_reserve.checked_add(amount_in),
+            )
+        };
+        pool.sol_reserve = sol_reserve.ok_or(AmmError::MathOverflow)?;
+        pool.token_reserve = token_reserve.ok_or(AmmError::MathOverflow)?;
+
+        msg!("Swapped {} in for {} out", amount_in, amount_out);
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    pub token_mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [POOL_SEED, token_mint.key().as_ref()],
+        bump,
+        space = 8 + std::mem::size_of::<Pool>(),
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [TOKEN_VAULT_SEED, pool.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = pool,
+    )]
+    pub token_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [LP_MINT_SEED, pool.key().as_ref()],
+        bump,
+        mint::decimals = token_mint.decimals,
+        mint::authority = pool,
+    )]
+    pub lp_mint: Account<'info, Mint>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct AddLiquidity<'info> {
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+
+    pub token_authority: Signer<'info>,
+
+    #[account(mut, token::mint = pool.token_mint)]
+    pub depositor_token: Account<'info, TokenAccount>,
+
+    #[account(mut, token::mint = lp_mint)]
+    pub lp_recipient: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        has_one = lp_mint,
+        seeds = [POOL_SEED, pool.token_mint.as_ref()],
+        bump = pool.bump,
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut, seeds = [TOKEN_VAULT_SEED, pool.key().as_ref()], bump)]
+    pub token_vault: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub lp_mint: Account<'info, Mint>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    #[account(mut)]
+    pub trader: Signer<'info>,
+
+    #[account(mut, token::mint = pool.token_mint, token::authority = trader)]
+    pub trader_token: Account<'info, TokenAccount>,
+
+    #[account(mut, seeds = [POOL_SEED, pool.token_mint.as_ref()], bump = pool.bump)]
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut, seeds = [TOKEN_VAULT_SEED, pool.key().as_ref()], bump)]
+    pub token_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[account]
+pub struct Pool {
+    pub token_mint: Pubkey,
+    pub lp_mint: Pubkey,
+    pub sol_reserve: u64, // lamports above the rent-exempt minimum of the pool account
+    pub token_reserve: u64,
+    pub bump: u8,
+}
+
+#[error_code]
+pub enum AmmError {
+    #[msg("Mathematical overflow during operations.")]
+    MathOverflow,
+
+    #[msg("The deposit mints no LP tokens or needs more than max_tokens.")]
+    InvalidDeposit,
+
+    #[msg("The swap output is below the minimum.")]
+    SlippageExceeded,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::hash::hash;
+    use anchor_lang::{Discriminator, ToAccountMetas};
+
+    // Callers such as seed_liquidity of the sale program build this instruction by hand
+    #[test]
+    fn add_liquidity_interface_is_stable() {
+        let keys: Vec<Pubkey> = (0..9).map(|_| Pubkey::new_unique()).collect();
+        let metas = accounts::AddLiquidity {
+            depositor: keys[0],
+            token_authority: keys[1],
+            depositor_token: keys[2],
+            lp_recipient: keys[3],
+            pool: keys[4],
+            token_vault: keys[5],
+            lp_mint: keys[6],
+            token_program: keys[7],
+            system_program: keys[8],
+        }
+        .to_account_metas(None);
+
+        let order: Vec<Pubkey> = metas.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(order, keys);
+        let signers: Vec<bool> = metas.iter().map(|meta| meta.is_signer).collect();
+        assert_eq!(
+            signers,
+            [true, true, false, false, false, false, false, false, false]
+        );
+        let writable: Vec<bool> = metas.iter().map(|meta| meta.is_writable).collect();
+        assert_eq!(
+            writable,
+            [true, false, true, true, true, true, true, false, false]
+        );
+
+        assert_eq!(
+            instruction::AddLiquidity::DISCRIMINATOR,
+            hash(b"global:add_liquidity").to_bytes()[..8]
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use anchor_lang::prelude::*;
+
+const STAKE_SEED: &[u8] = b"stake";
+
+declare_id!("9jkKCaepfvo2htdPQBx2jRmPaYveVd9rjRDjphSmrW3L");
+
+// Local stand-in for the governance staking program. The sale only reads the
+// StakePosition layout: discriminator, owner, amount.
+#[program]
+mod mock_staking {
+    use super::*;
+
+    // Sets the staked amount of the signer, creating the position on first use
+    pub fn set_stake(ctx: Context<SetStake>, amount: u64) -> Result<()> {
+        let position = &mut ctx.accounts.position;
+        position.owner = ctx.accounts.staker.key();
+        position.amount = amount;
+        position.bump = ctx.bumps.position;
+
+        msg!("{} staked {}", position.owner, amount);
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct SetStake<'info> {
+    #[account(mut)]
+    pub staker: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = staker,
+        seeds = [STAKE_SEED, staker.key().as_ref()],
+        bump,
+        space = 8 + std::mem::size_of::<StakePosition>(),
+    )]
+    pub position: Account<'info, StakePosition>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[account]
+pub struct StakePosition {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub bump: u8,
+}
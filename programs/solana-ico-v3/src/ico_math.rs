@@ -0,0 +1,895 @@
+// Pure pricing and accounting helpers shared by the sale programs, no accounts or CPI here
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::keccak::hashv;
+
+pub const MAX_BPS: u16 = 10_000;
+
+// Tokens bought for `lamports` at `price` lamports per whole token
+pub fn tokens_for_lamports(lamports: u64, price: u64, decimals: u8) -> Option<u64> {
+    let tokens = (lamports as u128)
+        .checked_mul(10u128.checked_pow(decimals as u32)?)?
+        .checked_div(price as u128)?;
+    u64::try_from(tokens).ok()
+}
+
+// Lamports owed for `tokens` at `price` lamports per whole token
+pub fn lamports_for_tokens(tokens: u64, price: u64, decimals: u8) -> Option<u64> {
+    let lamports =
+        (tokens as u128).checked_mul(price as u128)? / 10u128.checked_pow(decimals as u32)?;
+    u64::try_from(lamports).ok()
+}
+
+// Share of `amount` expressed in basis points
+pub fn bps_of(amount: u64, bps: u16) -> Option<u64> {
+    let share = (amount as u128).checked_mul(bps as u128)? / MAX_BPS as u128;
+    u64::try_from(share).ok()
+}
+
+// Tokens worth `lamports` at the average price of a sale that sold `total_sold` tokens
+// for `lamports_received`
+pub fn tokens_at_average_price(
+    lamports: u64,
+    total_sold: u64,
+    lamports_received: u64,
+) -> Option<u64> {
+    let tokens = (lamports as u128)
+        .checked_mul(total_sold as u128)?
+        .checked_div(lamports_received as u128)?;
+    u64::try_from(tokens).ok()
+}
+
+// Floor of the square root, by Newton's method
+pub fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    let mut x = n;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PriceCurve {
+    Linear,
+    // the premium above floor_price halves every half_life seconds
+    Exponential,
+}
+
+// Dutch auction parameters, prices are in lamports per whole token
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct DutchAuctionConfig {
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub start_price: u64,
+    pub floor_price: u64,
+    pub curve: PriceCurve,
+    pub half_life: i64,
+    pub uniform_clearing: bool, // escrow SOL and rebate buyers down to the clearing price
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct DutchAuction {
+    pub config: DutchAuctionConfig,
+    pub last_price: u64,
+    pub clearing_price: u64,
+    pub settled: bool,
+}
+
+impl DutchAuction {
+    // Price at `now`, None outside the auction window or once settled
+    pub fn price_at(&self, now: i64) -> Option<u64> {
+        let config = &self.config;
+        if now < config.start_ts || now >= config.end_ts || self.settled {
+            return None;
+        }
+
+        let elapsed = now.checked_sub(config.start_ts)? as u128;
+        let premium = config.start_price.checked_sub(config.floor_price)? as u128;
+        let remaining = match config.curve {
+            PriceCurve::Linear => {
+                let duration = config.end_ts.checked_sub(config.start_ts)? as u128;
+                premium.checked_sub(premium.checked_mul(elapsed)?.checked_div(duration)?)?
+            }
+            PriceCurve::Exponential => {
+                // halve per full half_life, then interpolate linearly inside the current one
+                let half_life = u128::try_from(config.half_life).ok()?;
+                let halvings = elapsed.checked_div(half_life)?;
+                let step = if halvings >= 128 {
+                    0
+                } else {
+                    premium >> halvings
+                };
+                step.checked_sub((step / 2).checked_mul(elapsed % half_life)? / half_life)?
+            }
+        };
+        config
+            .floor_price
+            .checked_add(u64::try_from(remaining).ok()?)
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CurveKind {
+    // price = base_price + slope * sold, in lamports per whole token
+    Linear,
+    // pump style curve on virtual reserves, price = sol_reserves / token_reserves
+    ConstantProduct,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct BondingCurve {
+    pub kind: CurveKind,
+    pub base_price: u64,
+    pub slope: u64, // lamports per whole token, per whole token sold
+    pub virtual_sol_reserves: u64,
+    pub virtual_token_reserves: u64,
+}
+
+impl BondingCurve {
+    // Lamports per whole token for the next token sold after `total_sold`
+    pub fn marginal_price(&self, total_sold: u64, decimals: u8) -> Option<u64> {
+        let unit = 10u128.checked_pow(decimals as u32)?;
+        let price = match self.kind {
+            CurveKind::Linear => (self.slope as u128)
+                .checked_mul(total_sold as u128)?
+                .checked_div(unit)?
+                .checked_add(self.base_price as u128)?,
+            CurveKind::ConstantProduct => {
+                let token_reserves =
+                    (self.virtual_token_reserves as u128).checked_sub(total_sold as u128)?;
+                if token_reserves == 0 {
+                    return Some(u64::MAX);
+                }
+                let k = (self.virtual_sol_reserves as u128)
+                    .checked_mul(self.virtual_token_reserves as u128)?;
+                k.div_ceil(token_reserves).checked_mul(unit)? / token_reserves
+            }
+        };
+        // a price above u64::MAX is as good as sold out
+        Some(u64::try_from(price).unwrap_or(u64::MAX))
+    }
+
+    // Lamports needed to buy `tokens` starting at `total_sold`, rounded up
+    pub fn cost(&self, tokens: u64, total_sold: u64, decimals: u8) -> Option<u64> {
+        let n = tokens as u128;
+        let sold = total_sold as u128;
+        let cost = match self.kind {
+            CurveKind::Linear => {
+                let unit = 10u128.checked_pow(decimals as u32)?;
+                let (b, _) = self.linear_terms(sold, 0, unit)?;
+                let scaled = (self.slope as u128)
+                    .checked_mul(n)?
+                    .checked_mul(n)?
+                    .checked_add(b.checked_mul(n)?)?;
+                scaled.div_ceil(unit.checked_mul(unit)?.checked_mul(2)?)
+            }
+            CurveKind::ConstantProduct => {
+                let token_reserves = (self.virtual_token_reserves as u128).checked_sub(sold)?;
+                let k = (self.virtual_sol_reserves as u128)
+                    .checked_mul(self.virtual_token_reserves as u128)?;
+                let remaining = token_reserves.checked_sub(n)?;
+                if remaining == 0 {
+                    return None;
+                }
+                k.div_ceil(remaining)
+                    .checked_sub(k.div_ceil(token_reserves))?
+            }
+        };
+        u64::try_from(cost).ok()
+    }
+
+    // Tokens bought for `lamports` starting at `total_sold`, integrating the curve
+    pub fn tokens_for_lamports(&self, lamports: u64, total_sold: u64, decimals: u8) -> Option<u64> {
+        let lamports = lamports as u128;
+        let sold = total_sold as u128;
+
+        let tokens = match self.kind {
+            CurveKind::Linear => {
+                // cost(sold, n) = (base * n + slope * (2 * sold * n + n^2) / (2 * D)) / D
+                // solve slope * n^2 + b * n - c = 0 for the largest n with cost <= lamports
+                let unit = 10u128.checked_pow(decimals as u32)?;
+                let slope = self.slope as u128;
+                let (b, c) = self.linear_terms(sold, lamports, unit)?;
+                let mut n = if slope == 0 {
+                    c.checked_div(b)?
+                } else {
+                    let discriminant = b
+                        .checked_mul(b)?
+                        .checked_add(slope.checked_mul(c)?.checked_mul(4)?)?;
+                    (isqrt(discriminant) - b) / slope.checked_mul(2)?
+                };
+                // integer sqrt can round up by one, never charge more than `lamports`
+                let scaled_cost = |n: u128| {
+                    slope
+                        .checked_mul(n)?
+                        .checked_mul(n)?
+                        .checked_add(b.checked_mul(n)?)
+                };
+                while n > 0 && scaled_cost(n).is_none_or(|cost| cost > c) {
+                    n -= 1;
+                }
+                n
+            }
+            CurveKind::ConstantProduct => {
+                let token_reserves = (self.virtual_token_reserves as u128).checked_sub(sold)?;
+                if token_reserves == 0 {
+                    return Some(0);
+                }
+                let k = (self.virtual_sol_reserves as u128)
+                    .checked_mul(self.virtual_token_reserves as u128)?;
+                let sol_reserves = k.div_ceil(token_reserves);
+                let new_sol_reserves = sol_reserves.checked_add(lamports)?;
+                token_reserves.checked_sub(k.div_ceil(new_sol_reserves))?
+            }
+        };
+        u64::try_from(tokens).ok()
+    }
+
+    // b = 2 * (slope * sold + base * D) and c = 2 * D^2 * lamports of the linear cost equation
+    fn linear_terms(&self, sold: u128, lamports: u128, unit: u128) -> Option<(u128, u128)> {
+        let b = (self.slope as u128)
+            .checked_mul(sold)?
+            .checked_add((self.base_price as u128).checked_mul(unit)?)?
+            .checked_mul(2)?;
+        let c = unit
+            .checked_mul(unit)?
+            .checked_mul(2)?
+            .checked_mul(lamports)?;
+        Some((b, c))
+    }
+}
+
+// Commit/claim sale: tokens_for_sale are shared pro-rata when commits exceed them
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct FairLaunch {
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub tokens_per_lamport: u64, // price fixed when the fair launch starts
+    pub tokens_for_sale: u64,
+    pub total_committed: u64,
+    pub tokens_allocated: u64,
+    pub finalized: bool,
+    pub committed_claimed: u64,
+    pub tokens_claimed: u64,
+}
+
+impl FairLaunch {
+    // Tokens and lamports used for a buyer who committed `committed` lamports
+    pub fn allocation(&self, committed: u64) -> Option<(u64, u64)> {
+        if self.tokens_per_lamport == 0 {
+            return None;
+        }
+        let wanted = (committed as u128).checked_mul(self.tokens_per_lamport as u128)?;
+        let pro_rata = (committed as u128)
+            .checked_mul(self.tokens_for_sale as u128)?
+            .checked_div(self.total_committed as u128)?;
+        let tokens = wanted.min(pro_rata);
+        let lamports_used = tokens
+            .div_ceil(self.tokens_per_lamport as u128)
+            .min(committed as u128);
+        Some((u64::try_from(tokens).ok()?, lamports_used as u64))
+    }
+
+    // Records a claim. Once every commitment has been claimed, returns the allocated
+    // tokens that per-claim rounding left in the reserve.
+    pub fn record_claim(&mut self, committed: u64, tokens: u64) -> Option<u64> {
+        let mut next = *self;
+        next.committed_claimed = next.committed_claimed.checked_add(committed)?;
+        next.tokens_claimed = next.tokens_claimed.checked_add(tokens)?;
+        let dust = if next.committed_claimed == next.total_committed {
+            next.tokens_allocated.checked_sub(next.tokens_claimed)?
+        } else {
+            0
+        };
+        *self = next;
+        Some(dust)
+    }
+}
+
+// Merkle leaf of an off-chain allocation: keccak(index || wallet || amount), little endian
+pub fn claim_leaf(index: u64, wallet: &Pubkey, amount: u64) -> [u8; 32] {
+    hashv(&[&index.to_le_bytes(), wallet.as_ref(), &amount.to_le_bytes()]).to_bytes()
+}
+
+// Walks the proof up to the root, hashing each pair in sorted order
+pub fn verify_merkle_proof(proof: &[[u8; 32]], root: &[u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof.iter().fold(leaf, |node, sibling| {
+        if node <= *sibling {
+            hashv(&[&node, sibling]).to_bytes()
+        } else {
+            hashv(&[sibling, &node]).to_bytes()
+        }
+    });
+    computed == *root
+}
+
+// Token and SOL counters of a sale. Every token that entered the vault is either still
+// available, unallocated, reserved for a fair launch or merkle claims, sold, paid as bonus,
+// airdropped, claimed, paired in the liquidity pool, settled as unsold or owed/paid to
+// referrers:
+// tokens_balance + unallocated_tokens + reserved_tokens + total_sold + bonus_tokens_distributed
+//     + airdropped_tokens + claimed_allocations + liquidity_tokens + unsold_tokens
+//     + referral_tokens_owed + referral_tokens_claimed == total_deposited - total_withdrawn
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct SaleLedger {
+    pub tokens_balance: u64,           // available for sale
+    pub reserved_tokens: u64,          // set aside for a fair launch or merkle claims until claimed
+    pub total_sold: u64,               // paid for with SOL, lamports_received is their price
+    pub bonus_tokens_distributed: u64, // bonus tokens are not counted in total_sold
+    pub referral_tokens_owed: u64,     // reserved in program ATA until referrers claim
+    pub referral_tokens_claimed: u64,
+    pub unallocated_tokens: u64, // sent to the vault outside deposit, not for sale
+    pub airdropped_tokens: u64,  // paid out of the balance by distribute_batch
+    pub liquidity_tokens: u64,   // deposited in the AMM pool by seed_liquidity
+    pub unsold_tokens: u64,      // burned, returned or moved when the sale was finalized
+    pub lamports_received: u64,
+    pub total_deposited: u64,
+    pub total_withdrawn: u64,
+    pub claimed_allocations: u64, // merkle allocations paid off-chain, not counted as sold
+}
+
+impl SaleLedger {
+    pub fn deposit(&mut self, amount: u64) -> Option<()> {
+        let mut next = *self;
+        next.tokens_balance = next.tokens_balance.checked_add(amount)?;
+        next.total_deposited = next.total_deposited.checked_add(amount)?;
+        *self = next;
+        Some(())
+    }
+
+    // Unallocated tokens are withdrawn first, then the balance for sale
+    pub fn withdraw(&mut self, amount: u64) -> Option<()> {
+        let mut next = *self;
+        let from_unallocated = amount.min(next.unallocated_tokens);
+        next.unallocated_tokens -= from_unallocated;
+        next.tokens_balance = next.tokens_balance.checked_sub(amount - from_unallocated)?;
+        next.total_withdrawn = next.total_withdrawn.checked_add(amount)?;
+        *self = next;
+        Some(())
+    }
+
+    pub fn withdrawable(&self) -> Option<u64> {
+        self.tokens_balance.checked_add(self.unallocated_tokens)
+    }
+
+    // Records tokens found in the vault beyond the counters as unallocated deposits and
+    // returns the surplus. None when the vault holds less than the counters.
+    pub fn reconcile(&mut self, vault_amount: u64) -> Option<u64> {
+        let surplus = vault_amount.checked_sub(self.vault_tokens()?)?;
+        let mut next = *self;
+        next.unallocated_tokens = next.unallocated_tokens.checked_add(surplus)?;
+        next.total_deposited = next.total_deposited.checked_add(surplus)?;
+        *self = next;
+        Some(surplus)
+    }
+
+    // Tokens leave the vault for the buyer, referral tokens stay in it until claimed
+    pub fn record_purchase(
+        &mut self,
+        tokens: u64,
+        bonus_tokens: u64,
+        referral_tokens: u64,
+        lamports: u64,
+    ) -> Option<()> {
+        let reserve = tokens
+            .checked_add(bonus_tokens)?
+            .checked_add(referral_tokens)?;
+        let mut next = *self;
+        next.tokens_balance = next.tokens_balance.checked_sub(reserve)?;
+        next.total_sold = next.total_sold.checked_add(tokens)?;
+        next.bonus_tokens_distributed = next.bonus_tokens_distributed.checked_add(bonus_tokens)?;
+        next.referral_tokens_owed = next.referral_tokens_owed.checked_add(referral_tokens)?;
+        next.lamports_received = next.lamports_received.checked_add(lamports)?;
+        *self = next;
+        Some(())
+    }
+
+    // Returned tokens and the referral reward taken back go to the balance, the refunded SOL
+    // is no longer received
+    pub fn record_sell_back(
+        &mut self,
+        sold_returned: u64,
+        bonus_returned: u64,
+        referral_returned: u64,
+        lamports_refunded: u64,
+    ) -> Option<()> {
+        let mut next = *self;
+        next.total_sold = next.total_sold.checked_sub(sold_returned)?;
+        next.bonus_tokens_distributed =
+            next.bonus_tokens_distributed.checked_sub(bonus_returned)?;
+        next.referral_tokens_owed = next.referral_tokens_owed.checked_sub(referral_returned)?;
+        next.lamports_received = next.lamports_received.checked_sub(lamports_refunded)?;
+        next.tokens_balance = next
+            .tokens_balance
+            .checked_add(sold_returned)?
+            .checked_add(bonus_returned)?
+            .checked_add(referral_returned)?;
+        *self = next;
+        Some(())
+    }
+
+    // SOL given back to a buyer is no longer received, the tokens stay sold
+    pub fn record_rebate(&mut self, lamports: u64) -> Option<()> {
+        let mut next = *self;
+        next.lamports_received = next.lamports_received.checked_sub(lamports)?;
+        *self = next;
+        Some(())
+    }
+
+    pub fn claim_referral_tokens(&mut self, amount: u64) -> Option<()> {
+        let mut next = *self;
+        next.referral_tokens_owed = next.referral_tokens_owed.checked_sub(amount)?;
+        next.referral_tokens_claimed = next.referral_tokens_claimed.checked_add(amount)?;
+        *self = next;
+        Some(())
+    }
+
+    pub fn reserve(&mut self, amount: u64) -> Option<()> {
+        let mut next = *self;
+        next.tokens_balance = next.tokens_balance.checked_sub(amount)?;
+        next.reserved_tokens = next.reserved_tokens.checked_add(amount)?;
+        *self = next;
+        Some(())
+    }
+
+    pub fn release_reserved(&mut self, amount: u64) -> Option<()> {
+        let mut next = *self;
+        next.reserved_tokens = next.reserved_tokens.checked_sub(amount)?;
+        next.tokens_balance = next.tokens_balance.checked_add(amount)?;
+        *self = next;
+        Some(())
+    }
+
+    // Reserved tokens leave the vault for a fair launch buyer
+    pub fn record_reserved_sale(&mut self, tokens: u64, lamports: u64) -> Option<()> {
+        let mut next = *self;
+        next.reserved_tokens = next.reserved_tokens.checked_sub(tokens)?;
+        next.total_sold = next.total_sold.checked_add(tokens)?;
+        next.lamports_received = next.lamports_received.checked_add(lamports)?;
+        *self = next;
+        Some(())
+    }
+
+    // Reserved tokens leave the vault for an allocation paid off-chain
+    pub fn record_claimed_allocation(&mut self, amount: u64) -> Option<()> {
+        let mut next = *self;
+        next.reserved_tokens = next.reserved_tokens.checked_sub(amount)?;
+        next.claimed_allocations = next.claimed_allocations.checked_add(amount)?;
+        *self = next;
+        Some(())
+    }
+
+    // Tokens paid from the balance to partners, outside of a sale
+    pub fn record_airdrop(&mut self, amount: u64) -> Option<()> {
+        let mut next = *self;
+        next.tokens_balance = next.tokens_balance.checked_sub(amount)?;
+        next.airdropped_tokens = next.airdropped_tokens.checked_add(amount)?;
+        *self = next;
+        Some(())
+    }
+
+    // Lamports and tokens to pair in the liquidity pool: `liquidity_bps` of the proceeds
+    // and the tokens they bought at the average price of the paid sales. Claimed
+    // allocations and bonus tokens cost nothing, so they would understate the price.
+    pub fn liquidity_pairing(&self, liquidity_bps: u16) -> Option<(u64, u64)> {
+        let lamports = bps_of(self.lamports_received, liquidity_bps)?;
+        let tokens = tokens_at_average_price(lamports, self.total_sold, self.lamports_received)?;
+        Some((lamports, tokens.min(self.tokens_balance)))
+    }
+
+    // Tokens paired with part of the proceeds in the liquidity pool
+    pub fn record_liquidity(&mut self, amount: u64) -> Option<()> {
+        let mut next = *self;
+        next.tokens_balance = next.tokens_balance.checked_sub(amount)?;
+        next.liquidity_tokens = next.liquidity_tokens.checked_add(amount)?;
+        *self = next;
+        Some(())
+    }
+
+    // Whatever is left for sale leaves the vault under the unsold policy
+    pub fn record_unsold(&mut self) -> Option<u64> {
+        let unsold = self.tokens_balance;
+        let mut next = *self;
+        next.unsold_tokens = next.unsold_tokens.checked_add(unsold)?;
+        next.tokens_balance = 0;
+        *self = next;
+        Some(unsold)
+    }
+
+    // Tokens the program ATA should hold according to the counters
+    pub fn vault_tokens(&self) -> Option<u64> {
+        self.tokens_balance
+            .checked_add(self.unallocated_tokens)?
+            .checked_add(self.reserved_tokens)?
+            .checked_add(self.referral_tokens_owed)
+    }
+
+    // Tokens that left the vault
+    pub fn tokens_distributed(&self) -> Option<u64> {
+        self.total_sold
+            .checked_add(self.bonus_tokens_distributed)?
+            .checked_add(self.airdropped_tokens)?
+            .checked_add(self.claimed_allocations)?
+            .checked_add(self.liquidity_tokens)?
+            .checked_add(self.unsold_tokens)?
+            .checked_add(self.referral_tokens_claimed)
+    }
+
+    pub fn is_balanced(&self) -> bool {
+        let in_vault = self.vault_tokens().map(u128::from);
+        let distributed = self.tokens_distributed().map(u128::from);
+        match (in_vault, distributed) {
+            (Some(in_vault), Some(distributed)) => {
+                self.total_deposited >= self.total_withdrawn
+                    && in_vault + distributed
+                        == (self.total_deposited - self.total_withdrawn) as u128
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Deposit(u64),
+        Withdraw(u64),
+        Purchase(u64, u64, u64, u64),
+        SellBack(u64, u64, u64, u64),
+        Rebate(u64),
+        ClaimReferral(u64),
+        Reserve(u64),
+        ReleaseReserved(u64),
+        ReservedSale(u64, u64),
+        ClaimedAllocation(u64),
+        Airdrop(u64),
+        Liquidity(u64),
+        Unsold,
+        Transfer(u64),
+        Reconcile,
+    }
+
+    fn amount() -> impl Strategy<Value = u64> {
+        prop_oneof![0..1_000_000u64, any::<u64>()]
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            amount().prop_map(Op::Deposit),
+            amount().prop_map(Op::Withdraw),
+            (amount(), amount(), amount(), amount())
+                .prop_map(|(t, b, r, l)| Op::Purchase(t, b, r, l)),
+            (amount(), amount(), amount(), amount())
+                .prop_map(|(t, b, r, l)| Op::SellBack(t, b, r, l)),
+            amount().prop_map(Op::Rebate),
+            amount().prop_map(Op::ClaimReferral),
+            amount().prop_map(Op::Reserve),
+            amount().prop_map(Op::ReleaseReserved),
+            (amount(), amount()).prop_map(|(t, l)| Op::ReservedSale(t, l)),
+            amount().prop_map(Op::ClaimedAllocation),
+            amount().prop_map(Op::Airdrop),
+            amount().prop_map(Op::Liquidity),
+            Just(Op::Unsold),
+            amount().prop_map(Op::Transfer),
+            Just(Op::Reconcile),
+        ]
+    }
+
+    fn bonding_curve() -> impl Strategy<Value = BondingCurve> {
+        prop_oneof![
+            (1..1_000_000_000u64, 0..1_000_000_000u64).prop_map(|(base_price, slope)| {
+                BondingCurve {
+                    kind: CurveKind::Linear,
+                    base_price,
+                    slope,
+                    virtual_sol_reserves: 0,
+                    virtual_token_reserves: 0,
+                }
+            }),
+            (1..u64::MAX, 1_000_000_000_000..u64::MAX).prop_map(|(sol, tokens)| BondingCurve {
+                kind: CurveKind::ConstantProduct,
+                base_price: 0,
+                slope: 0,
+                virtual_sol_reserves: sol,
+                virtual_token_reserves: tokens,
+            }),
+        ]
+    }
+
+    // Applies `op` to the counters, moving tokens in and out of the simulated vault
+    fn apply(ledger: &mut SaleLedger, vault: &mut u64, op: &Op) -> Option<()> {
+        let before = *ledger;
+        match *op {
+            Op::Transfer(amount) => {
+                // sent straight to the vault, the counters do not see it
+                *vault = vault.checked_add(amount)?;
+                return Some(());
+            }
+            Op::Reconcile => ledger.reconcile(*vault).map(|_| ())?,
+            Op::Deposit(amount) => ledger.deposit(amount)?,
+            Op::Withdraw(amount) => ledger.withdraw(amount)?,
+            Op::Purchase(tokens, bonus, referral, lamports) => {
+                ledger.record_purchase(tokens, bonus, referral, lamports)?
+            }
+            Op::SellBack(tokens, bonus, referral, lamports) => {
+                ledger.record_sell_back(tokens, bonus, referral, lamports)?
+            }
+            Op::Rebate(lamports) => ledger.record_rebate(lamports)?,
+            Op::ClaimReferral(amount) => ledger.claim_referral_tokens(amount)?,
+            Op::Reserve(amount) => ledger.reserve(amount)?,
+            Op::ReleaseReserved(amount) => ledger.release_reserved(amount)?,
+            Op::ReservedSale(tokens, lamports) => ledger.record_reserved_sale(tokens, lamports)?,
+            Op::ClaimedAllocation(amount) => ledger.record_claimed_allocation(amount)?,
+            Op::Airdrop(amount) => ledger.record_airdrop(amount)?,
+            Op::Liquidity(amount) => ledger.record_liquidity(amount)?,
+            Op::Unsold => ledger.record_unsold().map(|_| ())?,
+        }
+        let vault_after = (*vault as u128 + ledger.vault_tokens()? as u128)
+            .checked_sub(before.vault_tokens()? as u128)
+            .and_then(|amount| u64::try_from(amount).ok());
+        match vault_after {
+            Some(amount) => *vault = amount,
+            None => *ledger = before, // the token transfer itself would fail
+        }
+        vault_after.map(|_| ())
+    }
+
+    proptest! {
+        #[test]
+        fn ledger_stays_balanced(ops in proptest::collection::vec(op(), 0..64)) {
+            let mut ledger = SaleLedger::default();
+            let mut vault = 0u64;
+            for op in ops.iter() {
+                let before = ledger;
+                if apply(&mut ledger, &mut vault, op).is_none() {
+                    // a rejected operation leaves the counters untouched
+                    prop_assert_eq!(ledger, before);
+                }
+                prop_assert!(ledger.is_balanced(), "{:?} after {:?}", ledger, op);
+                // the counters never claim more than the vault holds
+                prop_assert!(ledger.vault_tokens().unwrap() <= vault);
+            }
+        }
+
+        #[test]
+        fn plain_sale_matches_deposits(
+            deposit in 0..u64::MAX / 2,
+            withdraw in any::<u64>(),
+            purchases in proptest::collection::vec(any::<u64>(), 0..16),
+        ) {
+            let mut ledger = SaleLedger::default();
+            ledger.deposit(deposit).unwrap();
+            let _ = ledger.withdraw(withdraw);
+            for tokens in purchases {
+                let _ = ledger.record_purchase(tokens, 0, 0, 0);
+            }
+            prop_assert_eq!(
+                ledger.tokens_balance + ledger.total_sold,
+                ledger.total_deposited - ledger.total_withdrawn
+            );
+        }
+
+        #[test]
+        fn price_math_never_panics(
+            amount in any::<u64>(),
+            price in any::<u64>(),
+            decimals in any::<u8>(),
+            bps in any::<u16>(),
+        ) {
+            let _ = tokens_for_lamports(amount, price, decimals);
+            let _ = lamports_for_tokens(amount, price, decimals);
+            let _ = bps_of(amount, bps);
+        }
+
+        #[test]
+        fn round_trip_never_overcharges(
+            lamports in any::<u64>(),
+            price in 1..u64::MAX,
+            decimals in 0..=12u8,
+        ) {
+            if let Some(tokens) = tokens_for_lamports(lamports, price, decimals) {
+                let cost = lamports_for_tokens(tokens, price, decimals).unwrap();
+                prop_assert!(cost <= lamports);
+            }
+        }
+
+        #[test]
+        fn average_price_share_is_bounded(
+            received in any::<u64>(),
+            total_sold in any::<u64>(),
+            bps in 0..=MAX_BPS,
+        ) {
+            let lamports = bps_of(received, bps).unwrap();
+            if let Some(tokens) = tokens_at_average_price(lamports, total_sold, received) {
+                prop_assert!(tokens <= total_sold);
+            } else {
+                prop_assert_eq!(received, 0);
+            }
+        }
+
+        #[test]
+        fn curve_never_charges_more_than_paid(
+            curve in bonding_curve(),
+            total_sold in 0..1_000_000_000_000u64,
+            lamports in any::<u64>(),
+            decimals in 0..=9u8,
+        ) {
+            if let Some(tokens) = curve.tokens_for_lamports(lamports, total_sold, decimals) {
+                let cost = curve.cost(tokens, total_sold, decimals).unwrap();
+                prop_assert!(cost <= lamports, "{} tokens cost {} > {}", tokens, cost, lamports);
+            }
+        }
+
+        #[test]
+        fn curve_price_never_falls(
+            curve in bonding_curve(),
+            total_sold in 0..1_000_000_000_000u64,
+            more in 0..1_000_000_000_000u64,
+            decimals in 0..=9u8,
+        ) {
+            if let (Some(before), Some(after)) = (
+                curve.marginal_price(total_sold, decimals),
+                curve.marginal_price(total_sold + more, decimals),
+            ) {
+                prop_assert!(after >= before);
+            }
+        }
+
+        #[test]
+        fn auction_price_only_decays(
+            start_price in 1..u64::MAX,
+            floor_bps in 1..=MAX_BPS,
+            duration in 1..1_000_000_000i64,
+            half_life in 1..1_000_000i64,
+            exponential in any::<bool>(),
+            t1 in 0..1_000_000_000i64,
+            t2 in 0..1_000_000_000i64,
+        ) {
+            let floor_price = bps_of(start_price, floor_bps).unwrap().max(1);
+            let auction = DutchAuction {
+                config: DutchAuctionConfig {
+                    start_ts: 0,
+                    end_ts: duration,
+                    start_price,
+                    floor_price,
+                    curve: if exponential { PriceCurve::Exponential } else { PriceCurve::Linear },
+                    half_life,
+                    uniform_clearing: false,
+                },
+                last_price: start_price,
+                clearing_price: 0,
+                settled: false,
+            };
+            let (early, late) = (t1.min(t2), t1.max(t2));
+            match (auction.price_at(early), auction.price_at(late)) {
+                (Some(early_price), Some(late_price)) => {
+                    prop_assert!(early_price >= late_price);
+                    prop_assert!(late_price >= floor_price && early_price <= start_price);
+                }
+                (early_price, _) => {
+                    prop_assert!(late >= duration);
+                    prop_assert_eq!(early_price.is_none(), early >= duration);
+                }
+            }
+        }
+
+        #[test]
+        fn fair_launch_shares_are_bounded(
+            commits in proptest::collection::vec(1..u64::MAX / 64, 1..32),
+            tokens_per_lamport in 1..1_000_000u64,
+            tokens_for_sale in any::<u64>(),
+        ) {
+            let total_committed: u64 = commits.iter().sum();
+            let demand = total_committed as u128 * tokens_per_lamport as u128;
+            let mut fair_launch = FairLaunch {
+                start_ts: 0,
+                end_ts: 1,
+                tokens_per_lamport,
+                tokens_for_sale,
+                total_committed,
+                tokens_allocated: demand.min(tokens_for_sale as u128) as u64,
+                finalized: true,
+                committed_claimed: 0,
+                tokens_claimed: 0,
+            };
+            let mut allocated = 0u128;
+            let mut dust = 0;
+            for committed in commits.iter() {
+                let (tokens, lamports_used) = fair_launch.allocation(*committed).unwrap();
+                prop_assert!(lamports_used <= *committed);
+                allocated += tokens as u128;
+                dust = fair_launch.record_claim(*committed, tokens).unwrap();
+            }
+            prop_assert!(allocated <= tokens_for_sale as u128);
+            // the last claim releases whatever rounding left in the reserve
+            prop_assert_eq!(allocated + dust as u128, fair_launch.tokens_allocated as u128);
+        }
+
+        #[test]
+        fn bps_share_is_bounded(amount in any::<u64>(), bps in 0..=MAX_BPS) {
+            prop_assert!(bps_of(amount, bps).unwrap() <= amount);
+        }
+
+        #[test]
+        fn merkle_proofs_verify_only_their_leaf(
+            amounts in proptest::collection::vec(1..u64::MAX, 1..24),
+            wrong_amount in any::<u64>(),
+        ) {
+            let wallets: Vec<Pubkey> = amounts.iter().map(|_| Pubkey::new_unique()).collect();
+            let leaves: Vec<[u8; 32]> = amounts
+                .iter()
+                .zip(wallets.iter())
+                .enumerate()
+                .map(|(index, (amount, wallet))| claim_leaf(index as u64, wallet, *amount))
+                .collect();
+
+            // build the tree level by level, an odd node is carried up as is
+            let mut levels = vec![leaves.clone()];
+            while levels.last().unwrap().len() > 1 {
+                let level = levels.last().unwrap();
+                let next = level
+                    .chunks(2)
+                    .map(|pair| match pair {
+                        [a, b] if a <= b => hashv(&[a, b]).to_bytes(),
+                        [a, b] => hashv(&[b, a]).to_bytes(),
+                        [a] => *a,
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                levels.push(next);
+            }
+            let root = levels.last().unwrap()[0];
+
+            for (index, leaf) in leaves.iter().enumerate() {
+                let mut proof = Vec::new();
+                let mut position = index;
+                for level in levels.iter().take(levels.len() - 1) {
+                    if let Some(sibling) = level.get(position ^ 1) {
+                        proof.push(*sibling);
+                    }
+                    position /= 2;
+                }
+                prop_assert!(verify_merkle_proof(&proof, &root, *leaf));
+
+                let forged = claim_leaf(index as u64, &wallets[index], wrong_amount);
+                if wrong_amount != amounts[index] {
+                    prop_assert!(!verify_merkle_proof(&proof, &root, forged));
+                }
+            }
+        }
+
+        #[test]
+        fn isqrt_is_floor_root(n in any::<u128>()) {
+            let root = isqrt(n);
+            prop_assert!(root * root <= n);
+            prop_assert!((root + 1).checked_mul(root + 1).is_none_or(|sq| sq > n));
+        }
+    }
+
+    #[test]
+    fn liquidity_pairs_at_the_paid_price() {
+        let mut ledger = SaleLedger::default();
+        ledger.deposit(10_000).unwrap();
+        ledger.reserve(4_000).unwrap();
+        ledger.record_claimed_allocation(3_000).unwrap();
+        assert!(ledger.is_balanced());
+        // 1_000 tokens for 2_000 lamports, with a bonus of 100
+        ledger.record_purchase(1_000, 100, 0, 2_000).unwrap();
+
+        // Half the proceeds buy 500 tokens, the claims and the bonus do not lower the price
+        assert_eq!(ledger.liquidity_pairing(5_000), Some((1_000, 500)));
+        // Capped by what is left for sale
+        ledger.record_airdrop(4_700).unwrap();
+        assert_eq!(ledger.liquidity_pairing(5_000), Some((1_000, 200)));
+        // Nothing was paid for
+        assert_eq!(SaleLedger::default().liquidity_pairing(5_000), None);
+    }
+}
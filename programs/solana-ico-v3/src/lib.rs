@@ -0,0 +1,3949 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::solana_program::instruction::{
+    get_stack_height, AccountMeta, Instruction, TRANSACTION_LEVEL_STACK_HEIGHT,
+};
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::solana_program::sysvar::instructions::{
+    load_current_index_checked, load_instruction_at_checked,
+};
+use anchor_lang::system_program::{transfer, Transfer};
+use anchor_lang::Discriminator;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, Mint, Token, TokenAccount};
+
+pub mod ico_math;
+use ico_math::{
+    bps_of, claim_leaf, lamports_for_tokens, tokens_for_lamports, verify_merkle_proof,
+    BondingCurve, CurveKind, DutchAuction, DutchAuctionConfig, FairLaunch, PriceCurve, SaleLedger,
+    MAX_BPS,
+};
+
+const ICO_MINT: &str = "AvEt25pkz91AaJM1K2bGcCGvm1AzfELFkQgKQEFUQc7n";
+const PROGRAM_ATA_SEED: &[u8] = b"program_ata";
+const ICO_PDA_SEED: &[u8] = b"ico_pda";
+const REFERRER_SEED: &[u8] = b"referrer";
+const CONTRIBUTION_SEED: &[u8] = b"contribution";
+const RECEIPT_SEED: &[u8] = b"receipt";
+const ATTESTATION_SEED: &[u8] = b"attestation";
+const CLAIM_BITMAP_SEED: &[u8] = b"claim_bitmap";
+const LP_LOCK_SEED: &[u8] = b"lp_lock";
+const LOCK_SEED: &[u8] = b"lock";
+const LOCK_VAULT_SEED: &[u8] = b"lock_vault";
+const SALE_METADATA_SEED: &[u8] = b"sale_metadata";
+const AMM_POOL_SEED: &[u8] = b"pool"; // pool PDA seed of the AMM program, per token mint
+const RECEIPT_HISTORY_LEN: usize = 8;
+const MAX_BONUS_TIERS: usize = 4;
+const MAX_STAKING_TIERS: usize = 4;
+const MAX_BATCH_RECIPIENTS: usize = 16;
+const MAX_CPI_CALLERS: usize = 4;
+const MAX_CLAIM_LEAVES: u32 = 64_000; // keeps the bitmap account under the 10 KiB init limit
+const KYC_PERMIT_MESSAGE_LEN: usize = 80;
+const JURISDICTION_WORDS: usize = 16; // bitmap over ISO 3166-1 numeric codes 0..1024
+const MAX_NAME_LEN: usize = 32;
+const MAX_SYMBOL_LEN: usize = 10;
+const MAX_URI_LEN: usize = 200;
+
+declare_id!("4bLbF6LwTuiPY5V63A7v4N8Uabcawt2HpjfobrjknLhm");
+
+#[program]
+mod ico_program {
+    use super::*;
+
+    // Initiates the Program, creates the program token account and initializes data
+    pub fn initiate_and_create_program_ata(
+        ctx: Context<InitiateAndCreateProgramATA>,
+        tokens_per_lamport: u64,
+        tokens_deposit_for_ico: u64,
+    ) -> Result<()> {
+        // Check for invalid SOL per token value
+        if tokens_per_lamport == 0 {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        // transfer tokens from admin ata to program ata
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.admin_ata.to_account_info(),
+                to: ctx.accounts.program_ata.to_account_info(),
+                authority: ctx.accounts.admin.to_account_info(),
+            },
+        );
+        token::transfer(cpi_ctx, tokens_deposit_for_ico)?;
+
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        ico_pda.admin = ctx.accounts.admin.key();
+        ico_pda.tokens_per_lamport = tokens_per_lamport;
+        ico_pda.lamports_per_token = 0; // initilly it will be 0
+        ico_pda.ledger = SaleLedger::default();
+        ico_pda
+            .ledger
+            .deposit(tokens_deposit_for_ico)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        ico_pda.decimals = ctx.accounts.ico_mint.decimals;
+        ico_pda.ata_bump = ctx.bumps.program_ata;
+
+        msg!(
+            "Program initiated with {} lamports per token and deposit {} tokens",
+            tokens_per_lamport,
+            tokens_deposit_for_ico
+        );
+        Ok(())
+    }
+
+    // Allows anyone to buy tokens with SOL and send SOL as lamports to the admin's account.
+    // When a KYC authority is set, the buyer must pass a permit signed by it, verified through
+    // an Ed25519 program instruction placed right before this one.
+    pub fn buy_with_sol(
+        ctx: Context<BuyWithSol>,
+        lamports: u64,
+        permit: Option<KycPermit>,
+        terms_hash: [u8; 32],
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        check_buyer(
+            &ctx.accounts.ico_pda,
+            &mut ctx.accounts.contribution,
+            lamports,
+            now,
+            BuyerChecks {
+                buyer: ctx.accounts.buyer.key(),
+                permit,
+                terms_hash,
+                stake_position: ctx.accounts.stake_position.as_ref(),
+                attestation: ctx.accounts.attestation.as_ref(),
+                instructions_sysvar: ctx.accounts.instructions_sysvar.as_ref(),
+            },
+        )?;
+        let ico_pda = &mut ctx.accounts.ico_pda;
+
+        // Calculate total cost and check for overflow, same math as quote_buy
+        let BuyQuote {
+            tokens_amount,
+            bonus_tokens,
+            price,
+        } = ico_pda.quote_buy(lamports, now)?;
+        if ico_pda.sale_mode == SaleMode::DutchAuction {
+            ico_pda.auction.last_price = price;
+        }
+
+        // Referral reward is paid either in sale tokens (reserved in the program ATA)
+        // or as a share of the lamports (escrowed in the referrer stats PDA)
+        let (referral_tokens, referral_lamports) = match &ctx.accounts.referrer_stats {
+            Some(referrer_stats) => {
+                if referrer_stats.referrer == ctx.accounts.buyer.key() {
+                    return Err(IcoCustomError::SelfReferral.into());
+                }
+                match ico_pda.referral_reward {
+                    ReferralReward::Tokens => (
+                        bps_of(tokens_amount, ico_pda.referral_bps)
+                            .ok_or(IcoCustomError::MathOverflow)?,
+                        0,
+                    ),
+                    ReferralReward::Lamports => (
+                        0,
+                        bps_of(lamports, ico_pda.referral_bps)
+                            .ok_or(IcoCustomError::MathOverflow)?,
+                    ),
+                }
+            }
+            None => (0, 0),
+        };
+        let tokens_to_deliver = tokens_amount
+            .checked_add(bonus_tokens)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        let tokens_to_reserve = tokens_to_deliver
+            .checked_add(referral_tokens)
+            .ok_or(IcoCustomError::MathOverflow)?;
+
+        // Check if buyer has enough lamports and enough tokens are available for purchase
+        if **ctx.accounts.buyer.try_borrow_mut_lamports()? < lamports
+            || ico_pda.ledger.tokens_balance < tokens_to_reserve
+        {
+            return Err(ProgramError::InsufficientFunds.into());
+        }
+
+        // Deduct/Transfer SOL from buyer and send to admin, or escrow it in the buyer's
+        // contribution PDA until a uniform clearing price auction is settled or while
+        // the purchase can still be sold back
+        let escrow_lamports =
+            ico_pda.sale_mode == SaleMode::DutchAuction && ico_pda.auction.config.uniform_clearing;
+        let sell_back_escrow = !escrow_lamports && ico_pda.sell_back_window > 0;
+        if ctx.accounts.contribution.escrow_to_release(
+            sell_back_escrow,
+            ico_pda.sell_back_window,
+            now,
+        )? {
+            release_sell_back_escrow(
+                &mut ctx.accounts.contribution,
+                &ctx.accounts.admin.to_account_info(),
+            )?;
+        }
+        let from_pubkey = ctx.accounts.buyer.to_account_info();
+        let to_pubkey = if escrow_lamports || sell_back_escrow {
+            ctx.accounts.contribution.to_account_info()
+        } else {
+            ctx.accounts.admin.to_account_info()
+        };
+        let program_id = ctx.accounts.system_program.to_account_info();
+        let cpi_context = CpiContext::new(
+            program_id,
+            Transfer {
+                from: from_pubkey,
+                to: to_pubkey,
+            },
+        );
+
+        transfer(cpi_context, lamports - referral_lamports)?;
+
+        // Update the buyer's contribution record
+        let contribution = &mut ctx.accounts.contribution;
+        contribution.buyer = ctx.accounts.buyer.key();
+        contribution.bump = ctx.bumps.contribution;
+        contribution.lamports_paid = contribution
+            .lamports_paid
+            .checked_add(lamports)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        contribution.tokens_bought = contribution
+            .tokens_bought
+            .checked_add(tokens_amount)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        if escrow_lamports {
+            contribution.escrowed_lamports = contribution
+                .escrowed_lamports
+                .checked_add(lamports - referral_lamports)
+                .ok_or(IcoCustomError::MathOverflow)?;
+            contribution.escrowed_tokens = contribution
+                .escrowed_tokens
+                .checked_add(tokens_amount)
+                .ok_or(IcoCustomError::MathOverflow)?;
+        }
+        if sell_back_escrow {
+            contribution.last_purchase_ts = now;
+            contribution.sell_back_lamports = contribution
+                .sell_back_lamports
+                .checked_add(lamports - referral_lamports)
+                .ok_or(IcoCustomError::MathOverflow)?;
+            contribution.sell_back_tokens = contribution
+                .sell_back_tokens
+                .checked_add(tokens_amount)
+                .ok_or(IcoCustomError::MathOverflow)?;
+            contribution.sell_back_bonus = contribution
+                .sell_back_bonus
+                .checked_add(bonus_tokens)
+                .ok_or(IcoCustomError::MathOverflow)?;
+            // The referral reward is taken back from the referrer on sell-back
+            contribution.sell_back_referrer = ctx
+                .accounts
+                .referrer_stats
+                .as_ref()
+                .map(|referrer_stats| referrer_stats.referrer)
+                .unwrap_or_default();
+            contribution.sell_back_referral_tokens = referral_tokens;
+            contribution.sell_back_referral_lamports = referral_lamports;
+        }
+
+        // Escrow the referrer's share of the SOL in the referrer stats PDA
+        if let Some(referrer_stats) = &mut ctx.accounts.referrer_stats {
+            if referral_lamports > 0 {
+                let cpi_context = CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.buyer.to_account_info(),
+                        to: referrer_stats.to_account_info(),
+                    },
+                );
+                transfer(cpi_context, referral_lamports)?;
+            }
+
+            referrer_stats.total_referrals = referrer_stats
+                .total_referrals
+                .checked_add(1)
+                .ok_or(IcoCustomError::MathOverflow)?;
+            referrer_stats.total_referred_lamports = referrer_stats
+                .total_referred_lamports
+                .checked_add(lamports)
+                .ok_or(IcoCustomError::MathOverflow)?;
+            referrer_stats.total_reward_tokens = referrer_stats
+                .total_reward_tokens
+                .checked_add(referral_tokens)
+                .ok_or(IcoCustomError::MathOverflow)?;
+            referrer_stats.unclaimed_tokens = referrer_stats
+                .unclaimed_tokens
+                .checked_add(referral_tokens)
+                .ok_or(IcoCustomError::MathOverflow)?;
+            referrer_stats.total_reward_lamports = referrer_stats
+                .total_reward_lamports
+                .checked_add(referral_lamports)
+                .ok_or(IcoCustomError::MathOverflow)?;
+            referrer_stats.unclaimed_lamports = referrer_stats
+                .unclaimed_lamports
+                .checked_add(referral_lamports)
+                .ok_or(IcoCustomError::MathOverflow)?;
+            if sell_back_escrow {
+                // Rewards can't be claimed while the purchase may still be sold back
+                let window_end = now
+                    .checked_add(ico_pda.sell_back_window)
+                    .ok_or(IcoCustomError::MathOverflow)?;
+                referrer_stats.claimable_ts = referrer_stats.claimable_ts.max(window_end);
+            }
+        }
+
+        // Get the seeds and bump for the Program ATA signer
+        let ico_mint_pubkey = ICO_MINT.parse::<Pubkey>().unwrap();
+        let seeds = &[
+            PROGRAM_ATA_SEED,
+            ico_mint_pubkey.as_ref(),
+            &[ico_pda.ata_bump],
+        ];
+        let signer = [&seeds[..]];
+        // Transfer tokens to the buyer's associated token account
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.program_ata.to_account_info(),
+                to: ctx.accounts.buyer_ata.to_account_info(),
+                authority: ctx.accounts.program_ata.to_account_info(),
+            },
+            &signer,
+        );
+        token::transfer(cpi_context, tokens_to_deliver)?;
+
+        // Update data for tokens sold and funds received
+        ico_pda
+            .ledger
+            .record_purchase(tokens_amount, bonus_tokens, referral_tokens, lamports)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        ico_pda.sale_started = true;
+
+        // Record the purchase in the buyer's receipt
+        let decimals = ico_pda.decimals;
+        let receipt = &mut ctx.accounts.receipt;
+        receipt.sale = ctx.accounts.ico_pda.key();
+        receipt.buyer = ctx.accounts.buyer.key();
+        receipt.record(
+            tokens_to_deliver,
+            lamports,
+            PaymentCurrency::Sol,
+            decimals,
+            &Clock::get()?,
+        )?;
+
+        assert_vault_covers_ledger(&ctx.accounts.ico_pda.ledger, &mut ctx.accounts.program_ata)?;
+
+        msg!(
+            "User bought {} tokens (+{} bonus) for {} lamports",
+            tokens_amount,
+            bonus_tokens,
+            lamports,
+        );
+        Ok(())
+    }
+
+    // Admin can withdraw tokens from the Program ATA account
+    pub fn withdraw_tokens(ctx: Context<WithdrawTokens>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        // Ensure enough tokens are available for withdrawal
+        let ico_pda = &ctx.accounts.ico_pda;
+        let withdrawable = ico_pda
+            .ledger
+            .withdrawable()
+            .ok_or(IcoCustomError::MathOverflow)?;
+        if withdrawable < amount {
+            return Err(ProgramError::InsufficientFunds.into());
+        }
+
+        // Get the seeds and bump for the Program ATA signer
+        let ico_mint_pubkey = ICO_MINT.parse::<Pubkey>().unwrap();
+        let seeds = &[
+            PROGRAM_ATA_SEED,
+            ico_mint_pubkey.as_ref(),
+            &[ico_pda.ata_bump],
+        ];
+        let signer = [&seeds[..]];
+        // Transfer tokens to the admin's associated token account
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.program_ata.to_account_info(),
+                to: ctx.accounts.admin_ata.to_account_info(),
+                authority: ctx.accounts.program_ata.to_account_info(),
+            },
+            &signer,
+        );
+        token::transfer(cpi_context, amount)?;
+
+        // Update data for the withdrawn amount
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        ico_pda
+            .ledger
+            .withdraw(amount)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        assert_vault_covers_ledger(&ctx.accounts.ico_pda.ledger, &mut ctx.accounts.program_ata)?;
+
+        msg!("Admin withdrew {} tokens from Program ATA", amount);
+        Ok(())
+    }
+
+    // Admin pays partners directly from the Program ATA. Recipient token accounts are passed
+    // as remaining accounts, in the same order as `amounts`.
+    pub fn distribute_batch<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DistributeBatch<'info>>,
+        amounts: Vec<u64>,
+    ) -> Result<()> {
+        let recipients = ctx.remaining_accounts;
+        if amounts.is_empty()
+            || amounts.len() > MAX_BATCH_RECIPIENTS
+            || amounts.len() != recipients.len()
+            || amounts.contains(&0)
+        {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+        let total = amounts
+            .iter()
+            .try_fold(0u64, |total, amount| total.checked_add(*amount))
+            .ok_or(IcoCustomError::MathOverflow)?;
+        if ctx.accounts.ico_pda.ledger.tokens_balance < total {
+            return Err(ProgramError::InsufficientFunds.into());
+        }
+
+        // Get the seeds and bump for the Program ATA signer
+        let ico_mint_pubkey = ICO_MINT.parse::<Pubkey>().unwrap();
+        let seeds = &[
+            PROGRAM_ATA_SEED,
+            ico_mint_pubkey.as_ref(),
+            &[ctx.accounts.ico_pda.ata_bump],
+        ];
+        let signer = [&seeds[..]];
+        for (recipient, amount) in recipients.iter().zip(amounts.iter()) {
+            // a self-transfer would be recorded as airdropped while the tokens stay in the vault
+            if recipient.key() == ctx.accounts.program_ata.key() {
+                return Err(ProgramError::InvalidArgument.into());
+            }
+            let recipient_ata = Account::<TokenAccount>::try_from(recipient)?;
+            if recipient_ata.mint != ico_mint_pubkey {
+                return Err(ProgramError::InvalidAccountData.into());
+            }
+
+            let cpi_context = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.program_ata.to_account_info(),
+                    to: recipient.clone(),
+                    authority: ctx.accounts.program_ata.to_account_info(),
+                },
+                &signer,
+            );
+            token::transfer(cpi_context, *amount)?;
+
+            emit!(BatchDistribution {
+                recipient: recipient_ata.owner,
+                token_account: recipient.key(),
+                amount: *amount,
+            });
+        }
+
+        ctx.accounts
+            .ico_pda
+            .ledger
+            .record_airdrop(total)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        assert_vault_covers_ledger(&ctx.accounts.ico_pda.ledger, &mut ctx.accounts.program_ata)?;
+
+        msg!(
+            "Admin distributed {} tokens to {} recipients",
+            total,
+            amounts.len()
+        );
+        Ok(())
+    }
+
+    // Admin publishes the merkle root of off-chain allocations and reserves their tokens.
+    // Every root gets a fresh claimed bitmap, so a replacement tree must leave out entries
+    // that were already claimed. The unclaimed reservation of the replaced root is released.
+    pub fn set_claim_root(
+        ctx: Context<SetClaimRoot>,
+        claim_root: [u8; 32],
+        leaf_count: u32,
+        total_amount: u64,
+    ) -> Result<()> {
+        if leaf_count == 0 || leaf_count > MAX_CLAIM_LEAVES || total_amount == 0 {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        if ico_pda.claim_root != [0; 32] {
+            // only the current root can be claimed, so its remainder goes back to the balance
+            let previous_bitmap = ctx
+                .accounts
+                .previous_bitmap
+                .as_mut()
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            let unclaimed = previous_bitmap.total_amount - previous_bitmap.claimed_amount;
+            ico_pda
+                .ledger
+                .release_reserved(unclaimed)
+                .ok_or(IcoCustomError::MathOverflow)?;
+            previous_bitmap.total_amount = previous_bitmap.claimed_amount;
+        }
+        if ico_pda.ledger.tokens_balance < total_amount {
+            return Err(ProgramError::InsufficientFunds.into());
+        }
+
+        // Reserve the allocated tokens so they cannot be withdrawn or sold elsewhere
+        ico_pda
+            .ledger
+            .reserve(total_amount)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        ico_pda.claim_root = claim_root;
+
+        let claim_bitmap = &mut ctx.accounts.claim_bitmap;
+        claim_bitmap.claim_root = claim_root;
+        claim_bitmap.leaf_count = leaf_count;
+        claim_bitmap.total_amount = total_amount;
+        claim_bitmap.bump = ctx.bumps.claim_bitmap;
+        claim_bitmap.claimed = vec![0; (leaf_count as usize).div_ceil(8)];
+
+        msg!(
+            "Admin set claim root for {} allocations of {} tokens",
+            leaf_count,
+            total_amount
+        );
+        Ok(())
+    }
+
+    // Wallet claims its off-chain allocation with a merkle proof of (index, wallet, amount)
+    pub fn claim_allocation(
+        ctx: Context<ClaimAllocation>,
+        index: u32,
+        amount: u64,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let claim_bitmap = &ctx.accounts.claim_bitmap;
+        if index >= claim_bitmap.leaf_count {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+        if claim_bitmap.is_claimed(index) {
+            return Err(IcoCustomError::AlreadyClaimed.into());
+        }
+        let leaf = claim_leaf(index as u64, &ctx.accounts.claimant.key(), amount);
+        if !verify_merkle_proof(&proof, &claim_bitmap.claim_root, leaf) {
+            return Err(IcoCustomError::InvalidMerkleProof.into());
+        }
+        let claimed_amount = claim_bitmap
+            .claimed_amount
+            .checked_add(amount)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        if claimed_amount > claim_bitmap.total_amount {
+            return Err(ProgramError::InsufficientFunds.into());
+        }
+
+        // Get the seeds and bump for the Program ATA signer
+        let ico_mint_pubkey = ICO_MINT.parse::<Pubkey>().unwrap();
+        let seeds = &[
+            PROGRAM_ATA_SEED,
+            ico_mint_pubkey.as_ref(),
+            &[ctx.accounts.ico_pda.ata_bump],
+        ];
+        let signer = [&seeds[..]];
+        // Transfer the allocation to the claimant's associated token account
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.program_ata.to_account_info(),
+                to: ctx.accounts.claimant_ata.to_account_info(),
+                authority: ctx.accounts.program_ata.to_account_info(),
+            },
+            &signer,
+        );
+        token::transfer(cpi_context, amount)?;
+
+        let claim_bitmap = &mut ctx.accounts.claim_bitmap;
+        claim_bitmap.set_claimed(index);
+        claim_bitmap.claimed_amount = claimed_amount;
+
+        // Paid off-chain, so the allocation is kept out of total_sold and the sale prices
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        ico_pda
+            .ledger
+            .record_claimed_allocation(amount)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        ico_pda.sale_started = true;
+
+        msg!("Allocation {} claimed: {} tokens", index, amount);
+        Ok(())
+    }
+
+    // Anyone can reconcile the counters with the program ATA, tokens sent to it directly
+    // are recorded as unallocated deposits that only the admin can withdraw
+    pub fn sync_balance(ctx: Context<SyncBalance>) -> Result<()> {
+        let vault_amount = ctx.accounts.program_ata.amount;
+        let surplus = ctx
+            .accounts
+            .ico_pda
+            .ledger
+            .reconcile(vault_amount)
+            .ok_or(IcoCustomError::VaultBalanceMismatch)?;
+
+        msg!(
+            "Program ATA holds {} tokens, {} recorded as unallocated",
+            vault_amount,
+            surplus
+        );
+        Ok(())
+    }
+
+    // Admin can deposit tokens in Program ATA account
+    pub fn deposit_tokens(ctx: Context<DepositTokens>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        // transfer tokens from admin ata to program ata
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.admin_ata.to_account_info(),
+                to: ctx.accounts.program_ata.to_account_info(),
+                authority: ctx.accounts.admin.to_account_info(),
+            },
+        );
+        token::transfer(cpi_ctx, amount)?;
+
+        // Update data for the deposited amount
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        ico_pda
+            .ledger
+            .deposit(amount)
+            .ok_or(IcoCustomError::MathOverflow)?;
+
+        msg!("Admin deposit {} tokens in Program ATA", amount);
+        Ok(())
+    }
+
+    // Admin update token price
+    pub fn update_price(ctx: Context<UpdatePrice>, tokens_per_lamport: u64) -> Result<()> {
+        if tokens_per_lamport == 0 {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        // Update data for the withdrawn amount
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        ico_pda.tokens_per_lamport = tokens_per_lamport;
+
+        msg!(
+            "Admin update token price {} token/lmports",
+            tokens_per_lamport
+        );
+        Ok(())
+    }
+
+    // Admin switches the sale to a Dutch auction, price decays from start_price to floor_price
+    pub fn start_dutch_auction(
+        ctx: Context<StartDutchAuction>,
+        config: DutchAuctionConfig,
+    ) -> Result<()> {
+        if config.start_ts >= config.end_ts
+            || config.floor_price == 0
+            || config.start_price < config.floor_price
+            || (config.curve == PriceCurve::Exponential && config.half_life <= 0)
+        {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        ico_pda.sale_mode = SaleMode::DutchAuction;
+        ico_pda.auction = DutchAuction {
+            config,
+            last_price: config.start_price,
+            clearing_price: 0,
+            settled: false,
+        };
+
+        msg!(
+            "Admin start dutch auction from {} to {} lamports per token",
+            config.start_price,
+            config.floor_price
+        );
+        Ok(())
+    }
+
+    // Anyone can fix the uniform clearing price once the auction ended or sold out
+    pub fn settle_dutch_auction(ctx: Context<SettleDutchAuction>) -> Result<()> {
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        if ico_pda.sale_mode != SaleMode::DutchAuction
+            || !ico_pda.auction.config.uniform_clearing
+            || ico_pda.auction.settled
+        {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        let sold_out = ico_pda.ledger.tokens_balance == 0;
+        if !sold_out && Clock::get()?.unix_timestamp < ico_pda.auction.config.end_ts {
+            return Err(IcoCustomError::AuctionNotEnded.into());
+        }
+        // Everyone pays the last (lowest) price a buyer actually paid, also when the auction
+        // ended without selling out: the floor may never have been reached by a purchase
+        ico_pda.auction.clearing_price = ico_pda.auction.last_price;
+        ico_pda.auction.settled = true;
+
+        msg!(
+            "Dutch auction settled at {} lamports per token",
+            ico_pda.auction.clearing_price
+        );
+        Ok(())
+    }
+
+    // Anyone can settle a buyer's escrow: the rebate above the clearing price goes back to the
+    // buyer and the rest to the admin
+    pub fn settle_auction_contribution(ctx: Context<SettleAuctionContribution>) -> Result<()> {
+        let ico_pda = &ctx.accounts.ico_pda;
+        if !ico_pda.auction.settled {
+            return Err(IcoCustomError::AuctionNotEnded.into());
+        }
+
+        let escrowed = ctx.accounts.contribution.escrowed_lamports;
+        if escrowed == 0 {
+            return Err(IcoCustomError::NothingToClaim.into());
+        }
+        let owed = lamports_for_tokens(
+            ctx.accounts.contribution.escrowed_tokens,
+            ico_pda.auction.clearing_price,
+            ico_pda.decimals,
+        )
+        .ok_or(IcoCustomError::MathOverflow)?
+        .min(escrowed);
+        let rebate = escrowed - owed;
+
+        // The contribution PDA is owned by this program, so its escrowed lamports are moved directly
+        **ctx
+            .accounts
+            .contribution
+            .to_account_info()
+            .try_borrow_mut_lamports()? -= escrowed;
+        **ctx.accounts.buyer.try_borrow_mut_lamports()? += rebate;
+        **ctx.accounts.admin.try_borrow_mut_lamports()? += owed;
+
+        let contribution = &mut ctx.accounts.contribution;
+        contribution.escrowed_lamports = 0;
+        contribution.escrowed_tokens = 0;
+        contribution.rebate_lamports = rebate;
+
+        // Update data for the refunded funds
+        ctx.accounts
+            .ico_pda
+            .ledger
+            .record_rebate(rebate)
+            .ok_or(IcoCustomError::MathOverflow)?;
+
+        msg!(
+            "Buyer rebated {} lamports, admin received {} lamports",
+            rebate,
+            owed
+        );
+        Ok(())
+    }
+
+    // Admin switches the sale to bonding curve pricing, price then follows total_sold
+    pub fn start_bonding_curve(
+        ctx: Context<StartBondingCurve>,
+        bonding_curve: BondingCurve,
+    ) -> Result<()> {
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        let valid = match bonding_curve.kind {
+            CurveKind::Linear => bonding_curve.base_price > 0,
+            CurveKind::ConstantProduct => {
+                bonding_curve.virtual_sol_reserves > 0
+                    && bonding_curve.virtual_token_reserves > ico_pda.ledger.total_sold
+            }
+        };
+        if !valid {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        ico_pda.sale_mode = SaleMode::BondingCurve;
+        ico_pda.bonding_curve = bonding_curve;
+
+        msg!("Admin start bonding curve sale");
+        Ok(())
+    }
+
+    // Admin sets when the sale ends (0 for no end) and what finalize_sale does with the tokens
+    // left for sale. `unsold_destination` is the token account used by UnsoldPolicy::MoveTo.
+    pub fn update_unsold_policy(
+        ctx: Context<UpdateUnsoldPolicy>,
+        sale_end_ts: i64,
+        unsold_policy: UnsoldPolicy,
+        unsold_destination: Pubkey,
+    ) -> Result<()> {
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        if sale_end_ts < 0 || ico_pda.sale_finalized {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        ico_pda.sale_end_ts = sale_end_ts;
+        ico_pda.unsold_policy = unsold_policy;
+        ico_pda.unsold_destination = unsold_destination;
+
+        msg!("Admin update sale end to {}", sale_end_ts);
+        Ok(())
+    }
+
+    // Admin sets the share of the proceeds seed_liquidity pairs with tokens in the AMM pool
+    // (0 disables it) and the AMM program it deposits into
+    pub fn update_liquidity(
+        ctx: Context<UpdateLiquidity>,
+        liquidity_bps: u16,
+        amm_program: Pubkey,
+    ) -> Result<()> {
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        if liquidity_bps > MAX_BPS || ico_pda.sale_finalized || ico_pda.liquidity_seeded {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        ico_pda.liquidity_bps = liquidity_bps;
+        ico_pda.amm_program = amm_program;
+
+        msg!(
+            "Admin update liquidity to {} bps in {}",
+            liquidity_bps,
+            amm_program
+        );
+        Ok(())
+    }
+
+    // After sale_end_ts and before finalize_sale, the admin deposits liquidity_bps of the SOL
+    // received and tokens at the average sale price into the AMM pool. The LP tokens are locked
+    // in a token account that is its own authority, no instruction of this program moves them.
+    pub fn seed_liquidity(ctx: Context<SeedLiquidity>) -> Result<()> {
+        let ico_pda = &ctx.accounts.ico_pda;
+        if ico_pda.liquidity_bps == 0
+            || ico_pda.liquidity_seeded
+            || ico_pda.sale_finalized
+            || ico_pda.sale_end_ts == 0
+        {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+        if Clock::get()?.unix_timestamp < ico_pda.sale_end_ts {
+            return Err(IcoCustomError::SaleNotEnded.into());
+        }
+        // Only the first deposit sets the pool price. Anyone can create and seed the pool at
+        // another ratio beforehand and then swap the admin's SOL out, so the pool must be empty.
+        // The admin can set liquidity_bps to 0 to finalize without it.
+        if ctx.accounts.lp_mint.supply > 0 {
+            return Err(IcoCustomError::PoolNotEmpty.into());
+        }
+
+        let (lamports, max_tokens) = ico_pda
+            .ledger
+            .liquidity_pairing(ico_pda.liquidity_bps)
+            .ok_or(ProgramError::InsufficientFunds)?;
+
+        // add_liquidity(lamports, max_tokens) of the AMM: the admin pays the SOL, the program
+        // ATA signs for the tokens
+        let mut data = hash(b"global:add_liquidity").to_bytes()[..8].to_vec();
+        data.extend_from_slice(&lamports.to_le_bytes());
+        data.extend_from_slice(&max_tokens.to_le_bytes());
+        let accounts = &ctx.accounts;
+        let add_liquidity = Instruction {
+            program_id: accounts.amm_program.key(),
+            accounts: vec![
+                AccountMeta::new(accounts.admin.key(), true),
+                AccountMeta::new(accounts.program_ata.key(), true),
+                AccountMeta::new(accounts.program_ata.key(), true),
+                AccountMeta::new(accounts.lp_lock.key(), false),
+                AccountMeta::new(accounts.pool.key(), false),
+                AccountMeta::new(accounts.pool_token_vault.key(), false),
+                AccountMeta::new(accounts.lp_mint.key(), false),
+                AccountMeta::new_readonly(accounts.token_program.key(), false),
+                AccountMeta::new_readonly(accounts.system_program.key(), false),
+            ],
+            data,
+        };
+
+        // Get the seeds and bump for the Program ATA signer
+        let ico_mint_pubkey = ICO_MINT.parse::<Pubkey>().unwrap();
+        let seeds = &[
+            PROGRAM_ATA_SEED,
+            ico_mint_pubkey.as_ref(),
+            &[ico_pda.ata_bump],
+        ];
+        let vault_before = accounts.program_ata.amount;
+        invoke_signed(
+            &add_liquidity,
+            &[
+                accounts.admin.to_account_info(),
+                accounts.program_ata.to_account_info(),
+                accounts.lp_lock.to_account_info(),
+                accounts.pool.to_account_info(),
+                accounts.pool_token_vault.to_account_info(),
+                accounts.lp_mint.to_account_info(),
+                accounts.token_program.to_account_info(),
+                accounts.system_program.to_account_info(),
+                accounts.amm_program.to_account_info(),
+            ],
+            &[&seeds[..]],
+        )?;
+
+        // The pool takes tokens at its own ratio, so record what actually left the vault
+        ctx.accounts.program_ata.reload()?;
+        ctx.accounts.lp_lock.reload()?;
+        let tokens = vault_before
+            .checked_sub(ctx.accounts.program_ata.amount)
+            .ok_or(IcoCustomError::VaultBalanceMismatch)?;
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        ico_pda
+            .ledger
+            .record_liquidity(tokens)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        ico_pda.liquidity_seeded = true;
+
+        assert_vault_covers_ledger(&ctx.accounts.ico_pda.ledger, &mut ctx.accounts.program_ata)?;
+
+        msg!(
+            "Seeded liquidity with {} lamports and {} tokens, {} LP tokens locked",
+            lamports,
+            tokens,
+            ctx.accounts.lp_lock.amount
+        );
+        Ok(())
+    }
+
+    // Anyone can finalize the sale after sale_end_ts, the tokens left for sale are burned,
+    // returned to the admin or moved to the unsold destination
+    pub fn finalize_sale(ctx: Context<FinalizeSale>) -> Result<()> {
+        let ico_pda = &ctx.accounts.ico_pda;
+        if ico_pda.sale_finalized || ico_pda.sale_end_ts == 0 {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+        if Clock::get()?.unix_timestamp < ico_pda.sale_end_ts {
+            return Err(IcoCustomError::SaleNotEnded.into());
+        }
+        if ico_pda.liquidity_bps > 0 && !ico_pda.liquidity_seeded {
+            return Err(IcoCustomError::LiquidityNotSeeded.into());
+        }
+        let unsold_policy = ico_pda.unsold_policy;
+        if unsold_policy != UnsoldPolicy::Burn {
+            let destination = ctx
+                .accounts
+                .destination
+                .as_ref()
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            let valid = match unsold_policy {
+                UnsoldPolicy::ReturnToAdmin => destination.owner == ico_pda.admin,
+                _ => destination.key() == ico_pda.unsold_destination,
+            };
+            if !valid {
+                return Err(ProgramError::InvalidAccountData.into());
+            }
+        }
+
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        let unsold = ico_pda
+            .ledger
+            .record_unsold()
+            .ok_or(IcoCustomError::MathOverflow)?;
+        ico_pda.sale_finalized = true;
+
+        if unsold > 0 {
+            // Get the seeds and bump for the Program ATA signer
+            let ico_mint_pubkey = ICO_MINT.parse::<Pubkey>().unwrap();
+            let seeds = &[
+                PROGRAM_ATA_SEED,
+                ico_mint_pubkey.as_ref(),
+                &[ico_pda.ata_bump],
+            ];
+            let signer = [&seeds[..]];
+            match (unsold_policy, &ctx.accounts.destination) {
+                (UnsoldPolicy::Burn, _) => {
+                    let cpi_context = CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        token::Burn {
+                            mint: ctx.accounts.ico_mint.to_account_info(),
+                            from: ctx.accounts.program_ata.to_account_info(),
+                            authority: ctx.accounts.program_ata.to_account_info(),
+                        },
+                        &signer,
+                    );
+                    token::burn(cpi_context, unsold)?;
+                }
+                (_, Some(destination)) => {
+                    let cpi_context = CpiContext::new_with_signer(
+                        ctx.accounts.token_program.to_account_info(),
+                        token::Transfer {
+                            from: ctx.accounts.program_ata.to_account_info(),
+                            to: destination.to_account_info(),
+                            authority: ctx.accounts.program_ata.to_account_info(),
+                        },
+                        &signer,
+                    );
+                    token::transfer(cpi_context, unsold)?;
+                }
+                (_, None) => return Err(ProgramError::NotEnoughAccountKeys.into()),
+            }
+        }
+
+        msg!("Sale finalized, {} unsold tokens handled", unsold);
+        Ok(())
+    }
+
+    // Locks `amount` tokens of any mint from the creator until `unlock_ts`, then only
+    // release_lock can move them and only to the beneficiary. One lock per creator,
+    // mint, beneficiary and unlock time.
+    pub fn create_lock(
+        ctx: Context<CreateLock>,
+        amount: u64,
+        beneficiary: Pubkey,
+        unlock_ts: i64,
+    ) -> Result<()> {
+        if amount == 0 || unlock_ts <= Clock::get()?.unix_timestamp {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        // Transfer tokens from the creator to the lock vault
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.creator_token.to_account_info(),
+                to: ctx.accounts.lock_vault.to_account_info(),
+                authority: ctx.accounts.creator.to_account_info(),
+            },
+        );
+        token::transfer(cpi_ctx, amount)?;
+
+        let lock = &mut ctx.accounts.lock;
+        lock.creator = ctx.accounts.creator.key();
+        lock.mint = ctx.accounts.mint.key();
+        lock.beneficiary = beneficiary;
+        lock.amount = amount;
+        lock.unlock_ts = unlock_ts;
+        lock.released = false;
+        lock.bump = ctx.bumps.lock;
+        lock.vault_bump = ctx.bumps.lock_vault;
+
+        msg!(
+            "Locked {} tokens of {} for {} until {}",
+            amount,
+            lock.mint,
+            beneficiary,
+            unlock_ts
+        );
+        Ok(())
+    }
+
+    // Anyone can release an expired lock, the whole vault goes to the beneficiary
+    pub fn release_lock(ctx: Context<ReleaseLock>) -> Result<()> {
+        let lock = &ctx.accounts.lock;
+        if lock.released {
+            return Err(IcoCustomError::NothingToClaim.into());
+        }
+        if Clock::get()?.unix_timestamp < lock.unlock_ts {
+            return Err(IcoCustomError::LockNotExpired.into());
+        }
+
+        // Get the seeds and bump for the lock vault signer
+        let lock_key = lock.key();
+        let seeds = &[LOCK_VAULT_SEED, lock_key.as_ref(), &[lock.vault_bump]];
+        let signer = [&seeds[..]];
+        let amount = ctx.accounts.lock_vault.amount;
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.lock_vault.to_account_info(),
+                to: ctx.accounts.beneficiary_token.to_account_info(),
+                authority: ctx.accounts.lock_vault.to_account_info(),
+            },
+            &signer,
+        );
+        token::transfer(cpi_context, amount)?;
+
+        ctx.accounts.lock.released = true;
+
+        msg!(
+            "Released {} locked tokens to {}",
+            amount,
+            ctx.accounts.lock.beneficiary
+        );
+        Ok(())
+    }
+
+    // Read-only: a lock and what its vault actually holds, returned as return data
+    pub fn get_lock(ctx: Context<GetLock>) -> Result<LockStatus> {
+        let lock = &ctx.accounts.lock;
+        let now = Clock::get()?.unix_timestamp;
+
+        Ok(LockStatus {
+            creator: lock.creator,
+            mint: lock.mint,
+            beneficiary: lock.beneficiary,
+            amount: lock.amount,
+            vault_balance: ctx.accounts.lock_vault.amount,
+            unlock_ts: lock.unlock_ts,
+            is_unlocked: now >= lock.unlock_ts,
+            released: lock.released,
+            timestamp: now,
+        })
+    }
+
+    // Read-only: what buy_with_sol would deliver for `lamports` right now, returned as return data
+    pub fn quote_buy(ctx: Context<QuoteBuy>, lamports: u64) -> Result<BuyQuote> {
+        let now = Clock::get()?.unix_timestamp;
+        ctx.accounts.ico_pda.quote_buy(lamports, now)
+    }
+
+    // Read-only: current sale state and price, returned as return data
+    pub fn get_sale_status(ctx: Context<GetSaleStatus>) -> Result<SaleStatus> {
+        let ico_pda = &ctx.accounts.ico_pda;
+        let now = Clock::get()?.unix_timestamp;
+        let is_open = !ico_pda.has_ended(now)
+            && match ico_pda.sale_mode {
+                SaleMode::FixedPrice | SaleMode::BondingCurve => ico_pda.ledger.tokens_balance > 0,
+                SaleMode::DutchAuction => ico_pda.auction.price_at(now).is_some(),
+                SaleMode::FairLaunch => {
+                    now >= ico_pda.fair_launch.start_ts && now < ico_pda.fair_launch.end_ts
+                }
+            };
+        // Outside its window an auction has no live price: report the clearing price once
+        // settled, 0 before that
+        let current_price = match ico_pda.sale_mode {
+            SaleMode::DutchAuction if !is_open => ico_pda.auction.clearing_price,
+            _ => ico_pda.current_price(now)?,
+        };
+
+        Ok(SaleStatus {
+            sale_mode: ico_pda.sale_mode,
+            is_open,
+            current_price,
+            tokens_per_lamport: ico_pda.tokens_per_lamport,
+            tokens_balance: ico_pda.ledger.tokens_balance,
+            total_sold: ico_pda.ledger.total_sold,
+            lamports_received: ico_pda.ledger.lamports_received,
+            bonus_tokens_distributed: ico_pda.ledger.bonus_tokens_distributed,
+            referral_tokens_owed: ico_pda.ledger.referral_tokens_owed,
+            timestamp: now,
+            claimed_allocations: ico_pda.ledger.claimed_allocations,
+        })
+    }
+
+    // Admin opens a fair launch: buyers commit SOL during the window and claim pro-rata after it
+    pub fn start_fair_launch(
+        ctx: Context<StartFairLaunch>,
+        start_ts: i64,
+        end_ts: i64,
+        tokens_for_sale: u64,
+    ) -> Result<()> {
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        if start_ts >= end_ts || tokens_for_sale == 0 || ico_pda.tokens_per_lamport == 0 {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+        if ico_pda.ledger.tokens_balance < tokens_for_sale {
+            return Err(ProgramError::InsufficientFunds.into());
+        }
+
+        // Reserve the offered tokens so they cannot be withdrawn or sold elsewhere
+        ico_pda
+            .ledger
+            .reserve(tokens_for_sale)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        ico_pda.sale_mode = SaleMode::FairLaunch;
+        ico_pda.fair_launch = FairLaunch {
+            start_ts,
+            end_ts,
+            tokens_per_lamport: ico_pda.tokens_per_lamport,
+            tokens_for_sale,
+            total_committed: 0,
+            tokens_allocated: 0,
+            finalized: false,
+            committed_claimed: 0,
+            tokens_claimed: 0,
+        };
+
+        msg!(
+            "Admin start fair launch of {} tokens at {} tokens/lamport",
+            tokens_for_sale,
+            ico_pda.tokens_per_lamport
+        );
+        Ok(())
+    }
+
+    // Buyers commit SOL into their contribution PDA while the fair launch window is open,
+    // behind the same checks as buy_with_sol
+    pub fn commit_sol(
+        ctx: Context<CommitSol>,
+        lamports: u64,
+        permit: Option<KycPermit>,
+        terms_hash: [u8; 32],
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        check_buyer(
+            &ctx.accounts.ico_pda,
+            &mut ctx.accounts.contribution,
+            lamports,
+            now,
+            BuyerChecks {
+                buyer: ctx.accounts.buyer.key(),
+                permit,
+                terms_hash,
+                stake_position: ctx.accounts.stake_position.as_ref(),
+                attestation: ctx.accounts.attestation.as_ref(),
+                instructions_sysvar: ctx.accounts.instructions_sysvar.as_ref(),
+            },
+        )?;
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        if ico_pda.sale_mode != SaleMode::FairLaunch
+            || now < ico_pda.fair_launch.start_ts
+            || now >= ico_pda.fair_launch.end_ts
+        {
+            return Err(IcoCustomError::FairLaunchNotActive.into());
+        }
+        if lamports == 0 {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.buyer.to_account_info(),
+                to: ctx.accounts.contribution.to_account_info(),
+            },
+        );
+        transfer(cpi_context, lamports)?;
+
+        let contribution = &mut ctx.accounts.contribution;
+        contribution.buyer = ctx.accounts.buyer.key();
+        contribution.bump = ctx.bumps.contribution;
+        contribution.committed_lamports = contribution
+            .committed_lamports
+            .checked_add(lamports)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        ico_pda.fair_launch.total_committed = ico_pda
+            .fair_launch
+            .total_committed
+            .checked_add(lamports)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        ico_pda.sale_started = true;
+
+        msg!("User committed {} lamports", lamports);
+        Ok(())
+    }
+
+    // Anyone can finalize the fair launch after end_ts, unsubscribed tokens return to the balance
+    pub fn finalize_fair_launch(ctx: Context<FinalizeFairLaunch>) -> Result<()> {
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        if ico_pda.sale_mode != SaleMode::FairLaunch || ico_pda.fair_launch.finalized {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+        if Clock::get()?.unix_timestamp < ico_pda.fair_launch.end_ts {
+            return Err(IcoCustomError::FairLaunchNotFinalized.into());
+        }
+
+        let demand = (ico_pda.fair_launch.total_committed as u128)
+            .checked_mul(ico_pda.fair_launch.tokens_per_lamport as u128)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        let tokens_for_sale = ico_pda.fair_launch.tokens_for_sale;
+        let tokens_allocated = demand.min(tokens_for_sale as u128) as u64;
+        ico_pda
+            .ledger
+            .release_reserved(tokens_for_sale - tokens_allocated)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        ico_pda.fair_launch.tokens_allocated = tokens_allocated;
+        ico_pda.fair_launch.finalized = true;
+
+        msg!(
+            "Fair launch finalized, {} of {} tokens allocated",
+            tokens_allocated,
+            tokens_for_sale
+        );
+        Ok(())
+    }
+
+    // Buyer claims min(commit, pro-rata share) tokens and gets back the excess SOL
+    pub fn claim_fair_launch(ctx: Context<ClaimFairLaunch>) -> Result<()> {
+        let ico_pda = &ctx.accounts.ico_pda;
+        if !ico_pda.fair_launch.finalized {
+            return Err(IcoCustomError::FairLaunchNotFinalized.into());
+        }
+        let committed = ctx.accounts.contribution.committed_lamports;
+        if committed == 0 {
+            return Err(IcoCustomError::NothingToClaim.into());
+        }
+
+        let (tokens_amount, lamports_used) = ico_pda
+            .fair_launch
+            .allocation(committed)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        let refund = committed - lamports_used;
+
+        // Get the seeds and bump for the Program ATA signer
+        let ico_mint_pubkey = ICO_MINT.parse::<Pubkey>().unwrap();
+        let seeds = &[
+            PROGRAM_ATA_SEED,
+            ico_mint_pubkey.as_ref(),
+            &[ico_pda.ata_bump],
+        ];
+        let signer = [&seeds[..]];
+        // Transfer tokens to the buyer's associated token account
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.program_ata.to_account_info(),
+                to: ctx.accounts.buyer_ata.to_account_info(),
+                authority: ctx.accounts.program_ata.to_account_info(),
+            },
+            &signer,
+        );
+        token::transfer(cpi_context, tokens_amount)?;
+
+        // The contribution PDA is owned by this program, so its escrowed lamports are moved directly
+        **ctx
+            .accounts
+            .contribution
+            .to_account_info()
+            .try_borrow_mut_lamports()? -= committed;
+        **ctx.accounts.buyer.try_borrow_mut_lamports()? += refund;
+        **ctx.accounts.admin.try_borrow_mut_lamports()? += lamports_used;
+
+        let contribution = &mut ctx.accounts.contribution;
+        contribution.committed_lamports = 0;
+        contribution.lamports_paid = contribution
+            .lamports_paid
+            .checked_add(lamports_used)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        contribution.tokens_bought = contribution
+            .tokens_bought
+            .checked_add(tokens_amount)
+            .ok_or(IcoCustomError::MathOverflow)?;
+
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        ico_pda
+            .ledger
+            .record_reserved_sale(tokens_amount, lamports_used)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        ico_pda.sale_started = true;
+        // Rounding each claim down leaves dust in the reserve, the last claim returns it
+        let dust = ico_pda
+            .fair_launch
+            .record_claim(committed, tokens_amount)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        ico_pda
+            .ledger
+            .release_reserved(dust)
+            .ok_or(IcoCustomError::MathOverflow)?;
+
+        // Record the purchase in the buyer's receipt
+        let decimals = ico_pda.decimals;
+        let receipt = &mut ctx.accounts.receipt;
+        receipt.sale = ctx.accounts.ico_pda.key();
+        receipt.buyer = ctx.accounts.buyer.key();
+        receipt.record(
+            tokens_amount,
+            lamports_used,
+            PaymentCurrency::Sol,
+            decimals,
+            &Clock::get()?,
+        )?;
+
+        msg!(
+            "User claimed {} tokens for {} lamports, refunded {} lamports",
+            tokens_amount,
+            lamports_used,
+            refund
+        );
+        Ok(())
+    }
+
+    // Admin sets the cooling-off period (0 disables sell-back) and the fee kept on sell-back
+    pub fn update_sell_back(
+        ctx: Context<UpdateSellBack>,
+        sell_back_window: i64,
+        sell_back_fee_bps: u16,
+    ) -> Result<()> {
+        if sell_back_window < 0 || sell_back_fee_bps > MAX_BPS {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        ico_pda.sell_back_window = sell_back_window;
+        ico_pda.sell_back_fee_bps = sell_back_fee_bps;
+
+        msg!(
+            "Admin update sell-back window to {} seconds with {} bps fee",
+            sell_back_window,
+            sell_back_fee_bps
+        );
+        Ok(())
+    }
+
+    // Buyer returns tokens bought within the sell-back window and gets the SOL back, minus the fee
+    pub fn sell_back(ctx: Context<SellBack>, tokens_amount: u64) -> Result<()> {
+        let ico_pda = &ctx.accounts.ico_pda;
+        let contribution = &ctx.accounts.contribution;
+        let now = Clock::get()?.unix_timestamp;
+        if now >= contribution.sell_back_window_end(ico_pda.sell_back_window)? {
+            return Err(IcoCustomError::SellBackWindowClosed.into());
+        }
+
+        // Returned tokens are split between purchased and bonus tokens in the same ratio
+        let tokens_delivered = contribution
+            .sell_back_tokens
+            .checked_add(contribution.sell_back_bonus)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        if tokens_amount == 0 || tokens_amount > tokens_delivered {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+        let share_of = |amount: u64| -> Result<u64> {
+            let share = (amount as u128)
+                .checked_mul(tokens_amount as u128)
+                .ok_or(IcoCustomError::MathOverflow)?
+                / tokens_delivered as u128;
+            Ok(share as u64)
+        };
+        let refund = share_of(contribution.sell_back_lamports)?;
+        let sold_returned = share_of(contribution.sell_back_tokens)?;
+        let bonus_returned = tokens_amount - sold_returned;
+        let fee = bps_of(refund, ico_pda.sell_back_fee_bps).ok_or(IcoCustomError::MathOverflow)?;
+        let referral_tokens_share = share_of(contribution.sell_back_referral_tokens)?;
+        let referral_lamports_share = share_of(contribution.sell_back_referral_lamports)?;
+
+        // The referrer gives back the same share of its reward, it can't have claimed it yet.
+        // A longer window set after the purchase can let the referrer claim first, only the
+        // unclaimed part is taken back then.
+        let (referral_returned, referral_refund) = match &ctx.accounts.referrer_stats {
+            Some(referrer_stats) => (
+                referral_tokens_share.min(referrer_stats.unclaimed_tokens),
+                referral_lamports_share.min(referrer_stats.unclaimed_lamports),
+            ),
+            None if referral_tokens_share > 0 || referral_lamports_share > 0 => {
+                return Err(ProgramError::NotEnoughAccountKeys.into())
+            }
+            None => (0, 0),
+        };
+        let lamports_returned = refund
+            .checked_add(referral_refund)
+            .ok_or(IcoCustomError::MathOverflow)?;
+
+        // Return tokens from the buyer's ATA to the program ATA
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.buyer_ata.to_account_info(),
+                to: ctx.accounts.program_ata.to_account_info(),
+                authority: ctx.accounts.buyer.to_account_info(),
+            },
+        );
+        token::transfer(cpi_ctx, tokens_amount)?;
+
+        // The contribution PDA is owned by this program, so its escrowed lamports are moved directly
+        **ctx
+            .accounts
+            .contribution
+            .to_account_info()
+            .try_borrow_mut_lamports()? -= refund;
+        **ctx.accounts.buyer.try_borrow_mut_lamports()? += lamports_returned - fee;
+        **ctx.accounts.admin.try_borrow_mut_lamports()? += fee;
+
+        if let Some(referrer_stats) = &mut ctx.accounts.referrer_stats {
+            // The stats PDA is owned by this program, so its escrowed lamports are moved directly
+            **referrer_stats.to_account_info().try_borrow_mut_lamports()? -= referral_refund;
+            referrer_stats.unclaimed_tokens -= referral_returned;
+            referrer_stats.unclaimed_lamports -= referral_refund;
+            referrer_stats.total_reward_tokens = referrer_stats
+                .total_reward_tokens
+                .saturating_sub(referral_returned);
+            referrer_stats.total_reward_lamports = referrer_stats
+                .total_reward_lamports
+                .saturating_sub(referral_refund);
+            referrer_stats.total_referred_lamports = referrer_stats
+                .total_referred_lamports
+                .saturating_sub(lamports_returned);
+        }
+
+        let contribution = &mut ctx.accounts.contribution;
+        contribution.sell_back_lamports -= refund;
+        contribution.sell_back_tokens -= sold_returned;
+        contribution.sell_back_bonus -= bonus_returned;
+        contribution.sell_back_referral_tokens -= referral_tokens_share;
+        contribution.sell_back_referral_lamports -= referral_lamports_share;
+        contribution.lamports_paid = contribution.lamports_paid.saturating_sub(lamports_returned);
+        contribution.tokens_bought = contribution.tokens_bought.saturating_sub(sold_returned);
+
+        // Update data for the returned tokens and refunded funds
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        ico_pda
+            .ledger
+            .record_sell_back(
+                sold_returned,
+                bonus_returned,
+                referral_returned,
+                lamports_returned - fee,
+            )
+            .ok_or(IcoCustomError::MathOverflow)?;
+
+        msg!(
+            "User sold back {} tokens for {} lamports ({} lamports fee)",
+            tokens_amount,
+            lamports_returned - fee,
+            fee
+        );
+        Ok(())
+    }
+
+    // Anyone can release a buyer's SOL to the admin once the sell-back window is over
+    pub fn release_sell_back(ctx: Context<ReleaseSellBack>) -> Result<()> {
+        let ico_pda = &ctx.accounts.ico_pda;
+        let contribution = &ctx.accounts.contribution;
+        let window_end = contribution.sell_back_window_end(ico_pda.sell_back_window)?;
+        if Clock::get()?.unix_timestamp < window_end {
+            return Err(IcoCustomError::SellBackWindowOpen.into());
+        }
+        if contribution.sell_back_lamports == 0 {
+            return Err(IcoCustomError::NothingToClaim.into());
+        }
+
+        let released = release_sell_back_escrow(
+            &mut ctx.accounts.contribution,
+            &ctx.accounts.admin.to_account_info(),
+        )?;
+
+        msg!("Released {} lamports to admin", released);
+        Ok(())
+    }
+
+    // Admin sets the bot protections of buy_with_sol, a zeroed config disables them all
+    pub fn update_anti_bot(ctx: Context<UpdateAntiBot>, anti_bot: AntiBot) -> Result<()> {
+        if anti_bot.max_buys_per_window > 0 && anti_bot.slot_window == 0 {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        ico_pda.anti_bot = anti_bot;
+
+        msg!(
+            "Admin update anti-bot: {} buys per {} slots, one buy per tx {}, block CPI {}",
+            anti_bot.max_buys_per_window,
+            anti_bot.slot_window,
+            anti_bot.one_buy_per_tx,
+            anti_bot.block_cpi
+        );
+        Ok(())
+    }
+
+    // Admin sets what wallets and explorers display for the sale and the hash of its legal
+    // terms, until the sale starts
+    pub fn set_sale_metadata(
+        ctx: Context<SetSaleMetadata>,
+        name: String,
+        symbol: String,
+        website_uri: String,
+        whitepaper_uri: String,
+        terms_hash: [u8; 32],
+    ) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        if ctx.accounts.ico_pda.has_started(now) {
+            return Err(IcoCustomError::SaleStarted.into());
+        }
+        if name.len() > MAX_NAME_LEN
+            || symbol.len() > MAX_SYMBOL_LEN
+            || website_uri.len() > MAX_URI_LEN
+            || whitepaper_uri.len() > MAX_URI_LEN
+        {
+            return Err(IcoCustomError::MetadataTooLong.into());
+        }
+
+        let sale_metadata = &mut ctx.accounts.sale_metadata;
+        sale_metadata.sale = ctx.accounts.ico_pda.key();
+        sale_metadata.name = name;
+        sale_metadata.symbol = symbol;
+        sale_metadata.website_uri = website_uri;
+        sale_metadata.whitepaper_uri = whitepaper_uri;
+        sale_metadata.terms_hash = terms_hash;
+        sale_metadata.bump = ctx.bumps.sale_metadata;
+        ctx.accounts.ico_pda.terms_hash = terms_hash;
+
+        msg!("Admin set sale metadata for {}", sale_metadata.name);
+        Ok(())
+    }
+
+    // Admin sets the KYC authority whose permits buy_with_sol requires (default pubkey disables KYC)
+    pub fn update_kyc_authority(
+        ctx: Context<UpdateKycAuthority>,
+        kyc_authority: Pubkey,
+    ) -> Result<()> {
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        ico_pda.kyc_authority = kyc_authority;
+
+        msg!("Admin update KYC authority to {}", kyc_authority);
+        Ok(())
+    }
+
+    // Admin sets the compliance authority issuing attestations (default pubkey disables the
+    // check) and the blocklist bitmap of jurisdictions that may not buy
+    pub fn update_compliance(
+        ctx: Context<UpdateCompliance>,
+        compliance_authority: Pubkey,
+        blocked_jurisdictions: Vec<u16>,
+    ) -> Result<()> {
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        ico_pda.set_jurisdiction_blocklist(&blocked_jurisdictions)?;
+        ico_pda.compliance_authority = compliance_authority;
+
+        msg!(
+            "Admin update compliance authority to {} with {} blocked jurisdictions",
+            compliance_authority,
+            blocked_jurisdictions.len()
+        );
+        Ok(())
+    }
+
+    // Compliance authority records (or renews) a buyer's jurisdiction and its expiry
+    pub fn issue_attestation(
+        ctx: Context<IssueAttestation>,
+        jurisdiction: u16,
+        expiry: i64,
+    ) -> Result<()> {
+        if jurisdiction as usize >= JURISDICTION_WORDS * 64 {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        let attestation = &mut ctx.accounts.attestation;
+        attestation.buyer = ctx.accounts.buyer.key();
+        attestation.authority = ctx.accounts.authority.key();
+        attestation.jurisdiction = jurisdiction;
+        attestation.expiry = expiry;
+        attestation.bump = ctx.bumps.attestation;
+
+        msg!(
+            "Attestation issued for {} in jurisdiction {} until {}",
+            attestation.buyer,
+            jurisdiction,
+            expiry
+        );
+        Ok(())
+    }
+
+    // Admin sets the staking program whose positions buy_with_sol reads (default pubkey
+    // disables tiers) and the tiers, ascending by min_staked
+    pub fn update_staking_tiers(
+        ctx: Context<UpdateStakingTiers>,
+        staking_program: Pubkey,
+        staking_tiers: Vec<StakingTier>,
+    ) -> Result<()> {
+        if staking_tiers.len() > MAX_STAKING_TIERS
+            || staking_tiers
+                .windows(2)
+                .any(|w| w[0].min_staked >= w[1].min_staked)
+        {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        ico_pda.staking_program = staking_program;
+        ico_pda.staking_tiers = [StakingTier::default(); MAX_STAKING_TIERS];
+        ico_pda.staking_tiers[..staking_tiers.len()].copy_from_slice(&staking_tiers);
+
+        msg!(
+            "Admin update staking program to {} with {} tiers",
+            staking_program,
+            staking_tiers.len()
+        );
+        Ok(())
+    }
+
+    // Admin sets the referral reward (in basis points) and whether it is paid in tokens or SOL
+    pub fn update_referral(
+        ctx: Context<UpdateReferral>,
+        referral_bps: u16,
+        referral_reward: ReferralReward,
+    ) -> Result<()> {
+        if referral_bps > MAX_BPS {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        ico_pda.referral_bps = referral_bps;
+        ico_pda.referral_reward = referral_reward;
+
+        msg!("Admin update referral reward to {} bps", referral_bps);
+        Ok(())
+    }
+
+    // Admin sets the bonus tiers, by time window (ascending end_ts) and by purchase size
+    // (ascending min_lamports). Unused tiers are cleared.
+    pub fn update_bonus_schedule(
+        ctx: Context<UpdateBonusSchedule>,
+        time_bonuses: Vec<TimeBonus>,
+        size_bonuses: Vec<SizeBonus>,
+    ) -> Result<()> {
+        if time_bonuses.len() > MAX_BONUS_TIERS || size_bonuses.len() > MAX_BONUS_TIERS {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+        if time_bonuses.iter().any(|tier| tier.bonus_bps > MAX_BPS)
+            || size_bonuses.iter().any(|tier| tier.bonus_bps > MAX_BPS)
+            || time_bonuses.windows(2).any(|w| w[0].end_ts >= w[1].end_ts)
+            || size_bonuses
+                .windows(2)
+                .any(|w| w[0].min_lamports >= w[1].min_lamports)
+        {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        let ico_pda = &mut ctx.accounts.ico_pda;
+        ico_pda.time_bonuses = [TimeBonus::default(); MAX_BONUS_TIERS];
+        ico_pda.time_bonuses[..time_bonuses.len()].copy_from_slice(&time_bonuses);
+        ico_pda.size_bonuses = [SizeBonus::default(); MAX_BONUS_TIERS];
+        ico_pda.size_bonuses[..size_bonuses.len()].copy_from_slice(&size_bonuses);
+
+        msg!(
+            "Admin update bonus schedule with {} time tiers and {} size tiers",
+            time_bonuses.len(),
+            size_bonuses.len()
+        );
+        Ok(())
+    }
+
+    // Anyone can register as a referrer, creating the stats PDA that buyers pass as referrer
+    pub fn register_referrer(ctx: Context<RegisterReferrer>) -> Result<()> {
+        let referrer_stats = &mut ctx.accounts.referrer_stats;
+        referrer_stats.referrer = ctx.accounts.referrer.key();
+        referrer_stats.bump = ctx.bumps.referrer_stats;
+
+        msg!("Referrer {} registered", referrer_stats.referrer);
+        Ok(())
+    }
+
+    // Referrer claims the unclaimed tokens and SOL credited by referred purchases
+    pub fn claim_referral_rewards(ctx: Context<ClaimReferralRewards>) -> Result<()> {
+        let unclaimed_tokens = ctx.accounts.referrer_stats.unclaimed_tokens;
+        let unclaimed_lamports = ctx.accounts.referrer_stats.unclaimed_lamports;
+        if unclaimed_tokens == 0 && unclaimed_lamports == 0 {
+            return Err(IcoCustomError::NothingToClaim.into());
+        }
+        if Clock::get()?.unix_timestamp < ctx.accounts.referrer_stats.claimable_ts {
+            return Err(IcoCustomError::SellBackWindowOpen.into());
+        }
+
+        if unclaimed_tokens > 0 {
+            // Get the seeds and bump for the Program ATA signer
+            let ico_mint_pubkey = ICO_MINT.parse::<Pubkey>().unwrap();
+            let seeds = &[
+                PROGRAM_ATA_SEED,
+                ico_mint_pubkey.as_ref(),
+                &[ctx.accounts.ico_pda.ata_bump],
+            ];
+            let signer = [&seeds[..]];
+            // Transfer reserved tokens to the referrer's associated token account
+            let cpi_context = CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token::Transfer {
+                    from: ctx.accounts.program_ata.to_account_info(),
+                    to: ctx.accounts.referrer_ata.to_account_info(),
+                    authority: ctx.accounts.program_ata.to_account_info(),
+                },
+                &signer,
+            );
+            token::transfer(cpi_context, unclaimed_tokens)?;
+
+            let ico_pda = &mut ctx.accounts.ico_pda;
+            ico_pda
+                .ledger
+                .claim_referral_tokens(unclaimed_tokens)
+                .ok_or(IcoCustomError::MathOverflow)?;
+        }
+
+        if unclaimed_lamports > 0 {
+            // The stats PDA is owned by this program, so its escrowed lamports are moved directly
+            **ctx
+                .accounts
+                .referrer_stats
+                .to_account_info()
+                .try_borrow_mut_lamports()? -= unclaimed_lamports;
+            **ctx
+                .accounts
+                .referrer
+                .to_account_info()
+                .try_borrow_mut_lamports()? += unclaimed_lamports;
+        }
+
+        let referrer_stats = &mut ctx.accounts.referrer_stats;
+        referrer_stats.unclaimed_tokens = 0;
+        referrer_stats.unclaimed_lamports = 0;
+
+        msg!(
+            "Referrer claimed {} tokens and {} lamports",
+            unclaimed_tokens,
+            unclaimed_lamports
+        );
+        Ok(())
+    }
+}
+
+// Moves the SOL escrowed for sell-back to the admin and closes the buyer's window, the
+// referral reward of the released purchases becomes final
+fn release_sell_back_escrow<'info>(
+    contribution: &mut Account<'info, Contribution>,
+    admin: &AccountInfo<'info>,
+) -> Result<u64> {
+    let released = contribution.sell_back_lamports;
+    **contribution.to_account_info().try_borrow_mut_lamports()? -= released;
+    **admin.try_borrow_mut_lamports()? += released;
+
+    contribution.sell_back_lamports = 0;
+    contribution.sell_back_tokens = 0;
+    contribution.sell_back_bonus = 0;
+    contribution.sell_back_referrer = Pubkey::default();
+    contribution.sell_back_referral_tokens = 0;
+    contribution.sell_back_referral_lamports = 0;
+    Ok(released)
+}
+
+// Arguments and optional accounts read by check_buyer
+struct BuyerChecks<'a, 'info> {
+    buyer: Pubkey,
+    permit: Option<KycPermit>,
+    terms_hash: [u8; 32],
+    stake_position: Option<&'a UncheckedAccount<'info>>,
+    attestation: Option<&'a Account<'info, ComplianceAttestation>>,
+    instructions_sysvar: Option<&'a UncheckedAccount<'info>>,
+}
+
+// Gates every way of paying into the sale, buy_with_sol and commit_sol alike: accepted terms,
+// anti-bot guards, KYC permit, staking tier cap and compliance attestation. The caps count
+// what the buyer paid or committed so far plus `lamports`.
+fn check_buyer(
+    ico_pda: &Account<IcoDataPda>,
+    contribution: &mut Contribution,
+    lamports: u64,
+    now: i64,
+    checks: BuyerChecks,
+) -> Result<()> {
+    // The buyer echoes the hash of the terms they accepted
+    if checks.terms_hash != ico_pda.terms_hash {
+        return Err(IcoCustomError::TermsNotAccepted.into());
+    }
+
+    let anti_bot = ico_pda.anti_bot;
+    if anti_bot.one_buy_per_tx || anti_bot.block_cpi {
+        let instructions_sysvar = checks
+            .instructions_sysvar
+            .ok_or(ProgramError::NotEnoughAccountKeys)?;
+        if anti_bot.block_cpi {
+            check_cpi_caller(instructions_sysvar, &anti_bot)?;
+        }
+        if anti_bot.one_buy_per_tx {
+            check_single_buy(instructions_sysvar)?;
+        }
+    }
+    if anti_bot.max_buys_per_window > 0 {
+        contribution.count_window_buy(&anti_bot, Clock::get()?.slot)?;
+    }
+
+    let paid = contribution
+        .lamports_paid
+        .checked_add(contribution.committed_lamports)
+        .and_then(|paid| paid.checked_add(lamports))
+        .ok_or(IcoCustomError::MathOverflow)?;
+
+    if ico_pda.kyc_authority != Pubkey::default() {
+        let permit = checks.permit.ok_or(IcoCustomError::KycPermitRequired)?;
+        let instructions_sysvar = checks
+            .instructions_sysvar
+            .ok_or(IcoCustomError::KycPermitRequired)?;
+        verify_kyc_permit(
+            instructions_sysvar,
+            &ico_pda.kyc_authority,
+            &ico_pda.key(),
+            &checks.buyer,
+            &permit,
+            now,
+        )?;
+        if paid > permit.max_allocation {
+            return Err(IcoCustomError::KycAllocationExceeded.into());
+        }
+    }
+
+    if ico_pda.staking_program != Pubkey::default() {
+        let stake_position = checks
+            .stake_position
+            .ok_or(IcoCustomError::StakePositionRequired)?;
+        let staked = staked_amount(stake_position, &ico_pda.staking_program, &checks.buyer)?;
+        let max_allocation = ico_pda
+            .staking_max_allocation(staked)
+            .ok_or(IcoCustomError::NoStakingTier)?;
+        if paid > max_allocation {
+            return Err(IcoCustomError::StakingAllocationExceeded.into());
+        }
+    }
+
+    if ico_pda.compliance_authority != Pubkey::default() {
+        let attestation = checks
+            .attestation
+            .ok_or(IcoCustomError::AttestationRequired)?;
+        ico_pda.check_attestation(attestation, now)?;
+    }
+    Ok(())
+}
+
+// Staked amount of `buyer` read from a StakePosition account of the staking program.
+// Layout: 8 byte Anchor discriminator, owner pubkey, amount (u64 le).
+fn staked_amount(
+    stake_position: &AccountInfo,
+    staking_program: &Pubkey,
+    buyer: &Pubkey,
+) -> Result<u64> {
+    if stake_position.owner != staking_program {
+        return Err(IcoCustomError::StakePositionRequired.into());
+    }
+    let data = stake_position.try_borrow_data()?;
+    let discriminator = &hash(b"account:StakePosition").to_bytes()[..8];
+    if data.len() < 48 || &data[..8] != discriminator || &data[8..40] != buyer.as_ref() {
+        return Err(IcoCustomError::StakePositionRequired.into());
+    }
+    Ok(u64::from_le_bytes(data[40..48].try_into().unwrap()))
+}
+
+// When called through CPI, the program of the top-level instruction must be allow-listed
+fn check_cpi_caller(instructions_sysvar: &AccountInfo, anti_bot: &AntiBot) -> Result<()> {
+    if get_stack_height() == TRANSACTION_LEVEL_STACK_HEIGHT {
+        return Ok(());
+    }
+    let current_index = load_current_index_checked(instructions_sysvar)?;
+    let caller =
+        load_instruction_at_checked(current_index as usize, instructions_sysvar)?.program_id;
+    if !anti_bot.allows_caller(&caller) {
+        return Err(IcoCustomError::CpiCallerNotAllowed.into());
+    }
+    Ok(())
+}
+
+// Fails when the transaction has more than one top-level buy_with_sol instruction of this
+// program. Buys made through CPI cannot be counted from the sysvar, so they are rejected.
+fn check_single_buy(instructions_sysvar: &AccountInfo) -> Result<()> {
+    if get_stack_height() != TRANSACTION_LEVEL_STACK_HEIGHT {
+        return Err(IcoCustomError::MultipleBuysInTransaction.into());
+    }
+    // the sysvar data starts with the number of instructions (u16 le)
+    let count = {
+        let data = instructions_sysvar.try_borrow_data()?;
+        u16::from_le_bytes([data[0], data[1]])
+    };
+    let mut buys = 0;
+    for index in 0..count {
+        let ix = load_instruction_at_checked(index as usize, instructions_sysvar)?;
+        if is_buy_instruction(&ix) {
+            buys += 1;
+        }
+    }
+    if buys > 1 {
+        return Err(IcoCustomError::MultipleBuysInTransaction.into());
+    }
+    Ok(())
+}
+
+// Whether `ix` pays into a sale of this program, buy_with_sol or commit_sol
+fn is_buy_instruction(ix: &Instruction) -> bool {
+    ix.program_id == crate::ID
+        && (ix.data.starts_with(&instruction::BuyWithSol::DISCRIMINATOR)
+            || ix.data.starts_with(&instruction::CommitSol::DISCRIMINATOR))
+}
+
+// Fails when the counters claim more tokens than the program ATA actually holds
+fn assert_vault_covers_ledger(
+    ledger: &SaleLedger,
+    program_ata: &mut Account<TokenAccount>,
+) -> Result<()> {
+    program_ata.reload()?;
+    let recorded = ledger.vault_tokens().ok_or(IcoCustomError::MathOverflow)?;
+    if recorded > program_ata.amount {
+        return Err(IcoCustomError::VaultBalanceMismatch.into());
+    }
+    Ok(())
+}
+
+// Checks that the instruction before the current one carries a valid, unexpired permit,
+// see check_ed25519_permit
+fn verify_kyc_permit(
+    instructions_sysvar: &AccountInfo,
+    kyc_authority: &Pubkey,
+    sale: &Pubkey,
+    buyer: &Pubkey,
+    permit: &KycPermit,
+    now: i64,
+) -> Result<()> {
+    let current_index = load_current_index_checked(instructions_sysvar)?;
+    if current_index == 0 {
+        return Err(IcoCustomError::KycPermitInvalid.into());
+    }
+    let ix = load_instruction_at_checked(current_index as usize - 1, instructions_sysvar)?;
+    check_ed25519_permit(&ix, kyc_authority, sale, buyer, permit, now)
+}
+
+// Checks that `ix` is an Ed25519 program instruction carrying exactly one signature by
+// `kyc_authority` over (sale, buyer, max_allocation, expiry), and that the permit has not
+// expired. All offsets must point into that same instruction, so the signed data cannot be
+// swapped.
+fn check_ed25519_permit(
+    ix: &Instruction,
+    kyc_authority: &Pubkey,
+    sale: &Pubkey,
+    buyer: &Pubkey,
+    permit: &KycPermit,
+    now: i64,
+) -> Result<()> {
+    if ix.program_id != ed25519_program::ID || !ix.accounts.is_empty() {
+        return Err(IcoCustomError::KycPermitInvalid.into());
+    }
+
+    // header: num_signatures (u8), padding (u8), then seven u16 offsets
+    let data = &ix.data;
+    if data.len() < 16 || data[0] != 1 {
+        return Err(IcoCustomError::KycPermitInvalid.into());
+    }
+    let read_u16 = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let signature_ix_index = read_u16(4);
+    let public_key_offset = read_u16(6) as usize;
+    let public_key_ix_index = read_u16(8);
+    let message_offset = read_u16(10) as usize;
+    let message_size = read_u16(12) as usize;
+    let message_ix_index = read_u16(14);
+    if signature_ix_index != u16::MAX
+        || public_key_ix_index != u16::MAX
+        || message_ix_index != u16::MAX
+        || message_size != KYC_PERMIT_MESSAGE_LEN
+    {
+        return Err(IcoCustomError::KycPermitInvalid.into());
+    }
+
+    let public_key = data
+        .get(public_key_offset..public_key_offset + 32)
+        .ok_or(IcoCustomError::KycPermitInvalid)?;
+    let message = data
+        .get(message_offset..message_offset + message_size)
+        .ok_or(IcoCustomError::KycPermitInvalid)?;
+    if public_key != kyc_authority.as_ref() || message != permit.message(sale, buyer) {
+        return Err(IcoCustomError::KycPermitInvalid.into());
+    }
+    if now >= permit.expiry {
+        return Err(IcoCustomError::KycPermitExpired.into());
+    }
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct InitiateAndCreateProgramATA<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(address = ICO_MINT.parse::<Pubkey>().unwrap())]
+    pub ico_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = ico_mint,
+        token::authority = admin,
+    )]
+    pub admin_ata: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = admin,
+        seeds = [PROGRAM_ATA_SEED, ICO_MINT.parse::<Pubkey>().unwrap().as_ref()],
+        bump,
+        token::mint = ico_mint,
+        token::authority = program_ata,
+    )]
+    pub program_ata: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = admin,
+        seeds = [ICO_PDA_SEED],
+        bump,
+        space = 8 + std::mem::size_of::<IcoDataPda>(),
+    )]
+    pub ico_pda: Account<'info, IcoDataPda>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct BuyWithSol<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(mut)]
+    pub admin: SystemAccount<'info>,
+
+    #[account(address = ICO_MINT.parse::<Pubkey>().unwrap())]
+    pub ico_mint: Account<'info, Mint>,
+
+    // created on the first purchase, the buyer pays the rent
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = ico_mint,
+        associated_token::authority = buyer,
+    )]
+    pub buyer_ata: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [PROGRAM_ATA_SEED, ICO_MINT.parse::<Pubkey>().unwrap().as_ref()],
+        bump= ico_pda.ata_bump,
+        )]
+    pub program_ata: Account<'info, TokenAccount>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        seeds = [CONTRIBUTION_SEED, buyer.key().as_ref()],
+        bump,
+        space = 8 + std::mem::size_of::<Contribution>(),
+    )]
+    pub contribution: Account<'info, Contribution>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        seeds = [RECEIPT_SEED, ico_pda.key().as_ref(), buyer.key().as_ref()],
+        bump,
+        space = 8 + std::mem::size_of::<BuyerReceipt>(),
+    )]
+    pub receipt: Box<Account<'info, BuyerReceipt>>,
+
+    // optional, the referrer who brought the buyer
+    #[account(
+        mut,
+        seeds = [REFERRER_SEED, referrer_stats.referrer.as_ref()],
+        bump = referrer_stats.bump,
+    )]
+    pub referrer_stats: Option<Account<'info, ReferrerStats>>,
+
+    /// CHECK: required when staking tiers are set, owner and layout checked in staked_amount
+    pub stake_position: Option<UncheckedAccount<'info>>,
+
+    // required when a compliance authority is set
+    #[account(seeds = [ATTESTATION_SEED, buyer.key().as_ref()], bump = attestation.bump)]
+    pub attestation: Option<Account<'info, ComplianceAttestation>>,
+
+    /// CHECK: required when KYC or the one-buy-per-tx or CPI guard is enabled
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawTokens<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        token::mint = ICO_MINT.parse::<Pubkey>().unwrap(),
+        token::authority = admin,
+    )]
+    pub admin_ata: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [PROGRAM_ATA_SEED, ICO_MINT.parse::<Pubkey>().unwrap().as_ref()],
+        bump= ico_pda.ata_bump,
+        )]
+    pub program_ata: Account<'info, TokenAccount>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DistributeBatch<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [PROGRAM_ATA_SEED, ICO_MINT.parse::<Pubkey>().unwrap().as_ref()],
+        bump= ico_pda.ata_bump,
+        )]
+    pub program_ata: Account<'info, TokenAccount>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(claim_root: [u8; 32], leaf_count: u32)]
+pub struct SetClaimRoot<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+
+    #[account(
+        init,
+        payer = admin,
+        seeds = [CLAIM_BITMAP_SEED, claim_root.as_ref()],
+        bump,
+        space = ClaimBitmap::space(leaf_count),
+    )]
+    pub claim_bitmap: Account<'info, ClaimBitmap>,
+
+    // required when a root is already set, its unclaimed tokens are released
+    #[account(
+        mut,
+        seeds = [CLAIM_BITMAP_SEED, ico_pda.claim_root.as_ref()],
+        bump = previous_bitmap.bump,
+    )]
+    pub previous_bitmap: Option<Account<'info, ClaimBitmap>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimAllocation<'info> {
+    #[account(mut)]
+    pub claimant: Signer<'info>,
+
+    #[account(address = ICO_MINT.parse::<Pubkey>().unwrap())]
+    pub ico_mint: Account<'info, Mint>,
+
+    // created on the first claim, the claimant pays the rent
+    #[account(
+        init_if_needed,
+        payer = claimant,
+        associated_token::mint = ico_mint,
+        associated_token::authority = claimant,
+    )]
+    pub claimant_ata: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [PROGRAM_ATA_SEED, ICO_MINT.parse::<Pubkey>().unwrap().as_ref()],
+        bump= ico_pda.ata_bump,
+        )]
+    pub program_ata: Account<'info, TokenAccount>,
+
+    #[account(mut, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+
+    #[account(
+        mut,
+        seeds = [CLAIM_BITMAP_SEED, ico_pda.claim_root.as_ref()],
+        bump = claim_bitmap.bump,
+    )]
+    pub claim_bitmap: Account<'info, ClaimBitmap>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SyncBalance<'info> {
+    #[account(
+        seeds = [PROGRAM_ATA_SEED, ICO_MINT.parse::<Pubkey>().unwrap().as_ref()],
+        bump= ico_pda.ata_bump,
+        )]
+    pub program_ata: Account<'info, TokenAccount>,
+
+    #[account(mut, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct DepositTokens<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        token::mint = ICO_MINT.parse::<Pubkey>().unwrap(),
+        token::authority = admin,
+    )]
+    pub admin_ata: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [PROGRAM_ATA_SEED, ICO_MINT.parse::<Pubkey>().unwrap().as_ref()],
+        bump= ico_pda.ata_bump,
+        )]
+    pub program_ata: Account<'info, TokenAccount>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePrice<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StartDutchAuction<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct SettleDutchAuction<'info> {
+    #[account(mut, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct SettleAuctionContribution<'info> {
+    #[account(
+        mut,
+        seeds = [CONTRIBUTION_SEED, buyer.key().as_ref()],
+        bump = contribution.bump,
+        has_one = buyer,
+    )]
+    pub contribution: Account<'info, Contribution>,
+
+    #[account(mut)]
+    pub buyer: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub admin: SystemAccount<'info>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct StartBondingCurve<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateUnsoldPolicy<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateLiquidity<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct SeedLiquidity<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(address = ICO_MINT.parse::<Pubkey>().unwrap())]
+    pub ico_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [PROGRAM_ATA_SEED, ICO_MINT.parse::<Pubkey>().unwrap().as_ref()],
+        bump= ico_pda.ata_bump,
+        )]
+    pub program_ata: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut, has_one = admin, has_one = amm_program, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Box<Account<'info, IcoDataPda>>,
+
+    /// CHECK: the AMM program set by update_liquidity
+    pub amm_program: UncheckedAccount<'info>,
+
+    /// CHECK: pool of the sale token in the AMM program, its state is checked by the AMM
+    #[account(
+        mut,
+        seeds = [AMM_POOL_SEED, ico_mint.key().as_ref()],
+        bump,
+        seeds::program = amm_program.key(),
+    )]
+    pub pool: UncheckedAccount<'info>,
+
+    /// CHECK: token reserve of the pool, checked by the AMM
+    #[account(mut)]
+    pub pool_token_vault: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub lp_mint: Box<Account<'info, Mint>>,
+
+    // holds the LP tokens for good, it is its own authority and nothing signs for it
+    #[account(
+        init,
+        payer = admin,
+        seeds = [LP_LOCK_SEED, lp_mint.key().as_ref()],
+        bump,
+        token::mint = lp_mint,
+        token::authority = lp_lock,
+    )]
+    pub lp_lock: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeSale<'info> {
+    #[account(mut, address = ICO_MINT.parse::<Pubkey>().unwrap())]
+    pub ico_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [PROGRAM_ATA_SEED, ICO_MINT.parse::<Pubkey>().unwrap().as_ref()],
+        bump= ico_pda.ata_bump,
+        )]
+    pub program_ata: Account<'info, TokenAccount>,
+
+    #[account(mut, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+
+    // admin token account or the unsold destination, not needed when burning
+    #[account(mut, token::mint = ico_mint)]
+    pub destination: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(amount: u64, beneficiary: Pubkey, unlock_ts: i64)]
+pub struct CreateLock<'info> {
+    #[account(mut)]
+    pub creator: Signer<'info>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = creator,
+    )]
+    pub creator_token: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = creator,
+        seeds = [
+            LOCK_SEED,
+            creator.key().as_ref(),
+            mint.key().as_ref(),
+            beneficiary.as_ref(),
+            unlock_ts.to_le_bytes().as_ref(),
+        ],
+        bump,
+        space = 8 + std::mem::size_of::<TokenLock>(),
+    )]
+    pub lock: Account<'info, TokenLock>,
+
+    #[account(
+        init,
+        payer = creator,
+        seeds = [LOCK_VAULT_SEED, lock.key().as_ref()],
+        bump,
+        token::mint = mint,
+        token::authority = lock_vault,
+    )]
+    pub lock_vault: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseLock<'info> {
+    #[account(
+        mut,
+        seeds = [
+            LOCK_SEED,
+            lock.creator.as_ref(),
+            lock.mint.as_ref(),
+            lock.beneficiary.as_ref(),
+            lock.unlock_ts.to_le_bytes().as_ref(),
+        ],
+        bump = lock.bump,
+    )]
+    pub lock: Account<'info, TokenLock>,
+
+    #[account(
+        mut,
+        seeds = [LOCK_VAULT_SEED, lock.key().as_ref()],
+        bump = lock.vault_bump,
+    )]
+    pub lock_vault: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = lock.mint,
+        token::authority = lock.beneficiary,
+    )]
+    pub beneficiary_token: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct GetLock<'info> {
+    #[account(
+        seeds = [
+            LOCK_SEED,
+            lock.creator.as_ref(),
+            lock.mint.as_ref(),
+            lock.beneficiary.as_ref(),
+            lock.unlock_ts.to_le_bytes().as_ref(),
+        ],
+        bump = lock.bump,
+    )]
+    pub lock: Account<'info, TokenLock>,
+
+    #[account(seeds = [LOCK_VAULT_SEED, lock.key().as_ref()], bump = lock.vault_bump)]
+    pub lock_vault: Account<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct QuoteBuy<'info> {
+    #[account(seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct GetSaleStatus<'info> {
+    #[account(seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct StartFairLaunch<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct CommitSol<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        seeds = [CONTRIBUTION_SEED, buyer.key().as_ref()],
+        bump,
+        space = 8 + std::mem::size_of::<Contribution>(),
+    )]
+    pub contribution: Account<'info, Contribution>,
+
+    #[account(mut, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+
+    /// CHECK: required when staking tiers are set, owner and layout checked in staked_amount
+    pub stake_position: Option<UncheckedAccount<'info>>,
+
+    // required when a compliance authority is set
+    #[account(seeds = [ATTESTATION_SEED, buyer.key().as_ref()], bump = attestation.bump)]
+    pub attestation: Option<Account<'info, ComplianceAttestation>>,
+
+    /// CHECK: required when KYC or the one-buy-per-tx or CPI guard is enabled
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeFairLaunch<'info> {
+    #[account(mut, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimFairLaunch<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(mut)]
+    pub admin: SystemAccount<'info>,
+
+    #[account(address = ICO_MINT.parse::<Pubkey>().unwrap())]
+    pub ico_mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = ico_mint,
+        associated_token::authority = buyer,
+    )]
+    pub buyer_ata: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [PROGRAM_ATA_SEED, ICO_MINT.parse::<Pubkey>().unwrap().as_ref()],
+        bump= ico_pda.ata_bump,
+        )]
+    pub program_ata: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [CONTRIBUTION_SEED, buyer.key().as_ref()],
+        bump = contribution.bump,
+        has_one = buyer,
+    )]
+    pub contribution: Account<'info, Contribution>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        seeds = [RECEIPT_SEED, ico_pda.key().as_ref(), buyer.key().as_ref()],
+        bump,
+        space = 8 + std::mem::size_of::<BuyerReceipt>(),
+    )]
+    pub receipt: Box<Account<'info, BuyerReceipt>>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSellBack<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct SellBack<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(mut)]
+    pub admin: SystemAccount<'info>,
+
+    #[account(
+        mut,
+        token::mint = ICO_MINT.parse::<Pubkey>().unwrap(),
+        token::authority = buyer,
+    )]
+    pub buyer_ata: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [PROGRAM_ATA_SEED, ICO_MINT.parse::<Pubkey>().unwrap().as_ref()],
+        bump= ico_pda.ata_bump,
+        )]
+    pub program_ata: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [CONTRIBUTION_SEED, buyer.key().as_ref()],
+        bump = contribution.bump,
+        has_one = buyer,
+    )]
+    pub contribution: Account<'info, Contribution>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+
+    // required when the purchases in the window paid a referral reward
+    #[account(
+        mut,
+        seeds = [REFERRER_SEED, contribution.sell_back_referrer.as_ref()],
+        bump = referrer_stats.bump,
+    )]
+    pub referrer_stats: Option<Account<'info, ReferrerStats>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseSellBack<'info> {
+    #[account(
+        mut,
+        seeds = [CONTRIBUTION_SEED, contribution.buyer.as_ref()],
+        bump = contribution.bump,
+    )]
+    pub contribution: Account<'info, Contribution>,
+
+    #[account(mut)]
+    pub admin: SystemAccount<'info>,
+
+    #[account(has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct SetSaleMetadata<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+
+    #[account(
+        init_if_needed,
+        payer = admin,
+        seeds = [SALE_METADATA_SEED],
+        bump,
+        space = SaleMetadata::SPACE,
+    )]
+    pub sale_metadata: Account<'info, SaleMetadata>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAntiBot<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateKycAuthority<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateCompliance<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct IssueAttestation<'info> {
+    #[account(
+        mut,
+        constraint = authority.key() == ico_pda.compliance_authority @ IcoCustomError::Unauthorized,
+    )]
+    pub authority: Signer<'info>,
+
+    pub buyer: SystemAccount<'info>,
+
+    #[account(seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        seeds = [ATTESTATION_SEED, buyer.key().as_ref()],
+        bump,
+        space = 8 + std::mem::size_of::<ComplianceAttestation>(),
+    )]
+    pub attestation: Account<'info, ComplianceAttestation>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateStakingTiers<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateReferral<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateBonusSchedule<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(mut, has_one = admin, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterReferrer<'info> {
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = referrer,
+        seeds = [REFERRER_SEED, referrer.key().as_ref()],
+        bump,
+        space = 8 + std::mem::size_of::<ReferrerStats>(),
+    )]
+    pub referrer_stats: Account<'info, ReferrerStats>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReferralRewards<'info> {
+    #[account(mut)]
+    pub referrer: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = referrer,
+        seeds = [REFERRER_SEED, referrer.key().as_ref()],
+        bump = referrer_stats.bump,
+    )]
+    pub referrer_stats: Account<'info, ReferrerStats>,
+
+    #[account(address = ICO_MINT.parse::<Pubkey>().unwrap())]
+    pub ico_mint: Account<'info, Mint>,
+
+    #[account(
+        init_if_needed,
+        payer = referrer,
+        associated_token::mint = ico_mint,
+        associated_token::authority = referrer,
+    )]
+    pub referrer_ata: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [PROGRAM_ATA_SEED, ICO_MINT.parse::<Pubkey>().unwrap().as_ref()],
+        bump= ico_pda.ata_bump,
+        )]
+    pub program_ata: Account<'info, TokenAccount>,
+
+    #[account(mut, seeds=[ICO_PDA_SEED], bump)]
+    pub ico_pda: Account<'info, IcoDataPda>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+// Data structure to hold ICO-related state
+#[account]
+pub struct IcoDataPda {
+    pub admin: Pubkey,
+    pub tokens_per_lamport: u64,
+    pub lamports_per_token: u64, // if token price increas morethen 1 lamports
+    pub ledger: SaleLedger,      // token and SOL counters, see ico_math
+    pub referral_bps: u16,
+    pub referral_reward: ReferralReward,
+    pub time_bonuses: [TimeBonus; MAX_BONUS_TIERS],
+    pub size_bonuses: [SizeBonus; MAX_BONUS_TIERS],
+    pub sale_mode: SaleMode,
+    pub auction: DutchAuction,
+    pub bonding_curve: BondingCurve,
+    pub fair_launch: FairLaunch,
+    pub sell_back_window: i64, // seconds after a purchase during which it can be sold back
+    pub sell_back_fee_bps: u16,
+    pub kyc_authority: Pubkey, // default pubkey when KYC is not required
+    pub compliance_authority: Pubkey, // default pubkey when attestations are not required
+    pub jurisdiction_blocklist: [u64; JURISDICTION_WORDS],
+    pub claim_root: [u8; 32], // merkle root of off-chain allocations, see set_claim_root
+    pub staking_program: Pubkey, // default pubkey when staking tiers are not used
+    pub staking_tiers: [StakingTier; MAX_STAKING_TIERS],
+    pub sale_end_ts: i64, // 0 when the sale has no end
+    pub unsold_policy: UnsoldPolicy,
+    pub unsold_destination: Pubkey,
+    pub sale_finalized: bool,
+    pub liquidity_bps: u16, // share of the SOL received paired with tokens in the AMM pool
+    pub amm_program: Pubkey,
+    pub liquidity_seeded: bool,
+    pub anti_bot: AntiBot,
+    pub terms_hash: [u8; 32], // copy of SaleMetadata::terms_hash, zeros when there are no terms
+    pub decimals: u8,
+    pub ata_bump: u8,
+    pub sale_started: bool, // set by the first purchase, commit or claim, never cleared
+}
+
+// How buy_with_sol prices tokens
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SaleMode {
+    FixedPrice,
+    DutchAuction,
+    BondingCurve,
+    FairLaunch,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PaymentCurrency {
+    Sol,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct PurchaseRecord {
+    pub amount: u64, // tokens delivered
+    pub price: u64,  // effective price paid, per whole token
+    pub currency: PaymentCurrency,
+    pub timestamp: i64,
+}
+
+// Per (sale, buyer) purchase history, the last RECEIPT_HISTORY_LEN purchases are kept.
+// The totals are gross: sell-backs and rebates are not subtracted, the Contribution holds
+// what the buyer keeps.
+#[account]
+pub struct BuyerReceipt {
+    pub sale: Pubkey,
+    pub buyer: Pubkey,
+    pub total_tokens: u64,   // every token delivered, before sell-backs
+    pub total_lamports: u64, // every lamport paid, before sell-backs and rebates
+    pub purchase_count: u64,
+    pub first_purchase_slot: u64,
+    pub last_purchase_slot: u64,
+    pub next_index: u8, // ring buffer slot written by the next purchase
+    pub history: [PurchaseRecord; RECEIPT_HISTORY_LEN],
+}
+
+impl BuyerReceipt {
+    pub fn record(
+        &mut self,
+        amount: u64,
+        paid: u64,
+        currency: PaymentCurrency,
+        decimals: u8,
+        clock: &Clock,
+    ) -> Result<()> {
+        if self.purchase_count == 0 {
+            self.first_purchase_slot = clock.slot;
+        }
+        self.last_purchase_slot = clock.slot;
+        self.purchase_count = self
+            .purchase_count
+            .checked_add(1)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        self.total_tokens = self
+            .total_tokens
+            .checked_add(amount)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        match currency {
+            PaymentCurrency::Sol => {
+                self.total_lamports = self
+                    .total_lamports
+                    .checked_add(paid)
+                    .ok_or(IcoCustomError::MathOverflow)?
+            }
+        }
+
+        let price = (paid as u128 * 10u128.pow(decimals as u32))
+            .checked_div(amount as u128)
+            .map_or(0, |price| u64::try_from(price).unwrap_or(u64::MAX));
+        self.history[self.next_index as usize] = PurchaseRecord {
+            amount,
+            price,
+            currency,
+            timestamp: clock.unix_timestamp,
+        };
+        self.next_index = ((self.next_index as usize + 1) % RECEIPT_HISTORY_LEN) as u8;
+        Ok(())
+    }
+}
+
+// Per-buyer purchase record
+#[account]
+pub struct Contribution {
+    pub buyer: Pubkey,
+    pub lamports_paid: u64,
+    pub tokens_bought: u64,
+    pub escrowed_lamports: u64, // held here until a uniform clearing auction is settled
+    pub escrowed_tokens: u64,   // bought in that auction, priced at the clearing price
+    pub rebate_lamports: u64,
+    pub committed_lamports: u64, // fair launch commit, held here until claimed
+    pub last_purchase_ts: i64,
+    pub sell_back_lamports: u64, // held here until the sell-back window closes
+    pub sell_back_tokens: u64,
+    pub sell_back_bonus: u64,
+    pub sell_back_referrer: Pubkey, // rewarded for the purchases in the sell-back window
+    pub sell_back_referral_tokens: u64,
+    pub sell_back_referral_lamports: u64,
+    pub window_start_slot: u64, // first slot of the current anti-bot rate limit window
+    pub window_buys: u16,
+    pub bump: u8,
+}
+
+impl Contribution {
+    // Counts a purchase at `slot` in the buyer's rate limit window, starting a new window
+    // once `slot_window` slots have passed
+    pub fn count_window_buy(&mut self, anti_bot: &AntiBot, slot: u64) -> Result<()> {
+        let window_end = self.window_start_slot.saturating_add(anti_bot.slot_window);
+        if slot >= window_end {
+            self.window_start_slot = slot;
+            self.window_buys = 0;
+        }
+        if self.window_buys >= anti_bot.max_buys_per_window {
+            return Err(IcoCustomError::PurchaseRateLimited.into());
+        }
+        self.window_buys += 1;
+        Ok(())
+    }
+
+    pub fn sell_back_window_end(&self, sell_back_window: i64) -> Result<i64> {
+        self.last_purchase_ts
+            .checked_add(sell_back_window)
+            .ok_or(error!(IcoCustomError::MathOverflow))
+    }
+
+    // Whether the escrow of the earlier purchase must be released before a purchase at `now`.
+    // A buyer can't escrow a new purchase while the earlier one can still be sold back, so
+    // neither window is cut short nor stretched.
+    pub fn escrow_to_release(
+        &self,
+        sell_back_escrow: bool,
+        sell_back_window: i64,
+        now: i64,
+    ) -> Result<bool> {
+        let window_open =
+            self.sell_back_lamports > 0 && now < self.sell_back_window_end(sell_back_window)?;
+        if window_open && sell_back_escrow {
+            return Err(IcoCustomError::SellBackWindowOpen.into());
+        }
+        Ok(!window_open)
+    }
+}
+
+impl IcoDataPda {
+    // Tokens delivered by buy_with_sol for `lamports` at `now`, shared by quote_buy
+    pub fn quote_buy(&self, lamports: u64, now: i64) -> Result<BuyQuote> {
+        if self.has_ended(now) {
+            return Err(IcoCustomError::SaleEnded.into());
+        }
+        let price = self.current_price(now)?;
+        let tokens_amount = match self.sale_mode {
+            SaleMode::FixedPrice => {
+                if lamports == 0 && self.tokens_per_lamport > 0 {
+                    return Err(ProgramError::InvalidArgument.into());
+                }
+                lamports
+                    .checked_mul(self.tokens_per_lamport)
+                    .ok_or(IcoCustomError::MathOverflow)?
+            }
+            SaleMode::DutchAuction => tokens_for_lamports(lamports, price, self.decimals)
+                .ok_or(IcoCustomError::MathOverflow)?,
+            SaleMode::BondingCurve => self
+                .bonding_curve
+                .tokens_for_lamports(lamports, self.ledger.total_sold, self.decimals)
+                .ok_or(IcoCustomError::MathOverflow)?,
+            // purchases go through commit_sol / claim_fair_launch
+            SaleMode::FairLaunch => return Err(ProgramError::InvalidArgument.into()),
+        };
+        if tokens_amount == 0 && self.sale_mode != SaleMode::FixedPrice {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        // Early-bird and volume bonus, delivered on top of the purchased tokens
+        let bonus_tokens = bps_of(tokens_amount, self.bonus_bps(lamports, now))
+            .ok_or(IcoCustomError::MathOverflow)?;
+
+        Ok(BuyQuote {
+            tokens_amount,
+            bonus_tokens,
+            price,
+        })
+    }
+
+    // Whether buyers may have taken part already: someone bought, committed or claimed, or
+    // the auction or fair launch window has opened. The sale metadata is frozen from then
+    // on, sell-backs and rebates that return the lamports do not unfreeze it.
+    pub fn has_started(&self, now: i64) -> bool {
+        let start_ts = match self.sale_mode {
+            SaleMode::DutchAuction => Some(self.auction.config.start_ts),
+            SaleMode::FairLaunch => Some(self.fair_launch.start_ts),
+            SaleMode::FixedPrice | SaleMode::BondingCurve => None,
+        };
+        self.sale_started || start_ts.is_some_and(|start_ts| now >= start_ts)
+    }
+
+    pub fn has_ended(&self, now: i64) -> bool {
+        self.sale_finalized || (self.sale_end_ts > 0 && now >= self.sale_end_ts)
+    }
+
+    // Lamports a buyer with `staked` tokens may pay in total: the highest tier reached
+    pub fn staking_max_allocation(&self, staked: u64) -> Option<u64> {
+        self.staking_tiers
+            .iter()
+            .filter(|tier| tier.max_allocation > 0)
+            .rev()
+            .find(|tier| staked >= tier.min_staked)
+            .map(|tier| tier.max_allocation)
+    }
+
+    pub fn set_jurisdiction_blocklist(&mut self, codes: &[u16]) -> Result<()> {
+        let mut blocklist = [0; JURISDICTION_WORDS];
+        for code in codes.iter() {
+            let code = *code as usize;
+            if code >= JURISDICTION_WORDS * 64 {
+                return Err(ProgramError::InvalidArgument.into());
+            }
+            blocklist[code / 64] |= 1 << (code % 64);
+        }
+        self.jurisdiction_blocklist = blocklist;
+        Ok(())
+    }
+
+    // Codes past the bitmap cannot be allowed, so they count as blocked
+    pub fn is_jurisdiction_blocked(&self, jurisdiction: u16) -> bool {
+        let code = jurisdiction as usize;
+        code >= JURISDICTION_WORDS * 64
+            || self.jurisdiction_blocklist[code / 64] & (1 << (code % 64)) != 0
+    }
+
+    // The attestation must come from the compliance authority, be unexpired at `now` and
+    // name a jurisdiction that is not blocked
+    pub fn check_attestation(&self, attestation: &ComplianceAttestation, now: i64) -> Result<()> {
+        if attestation.authority != self.compliance_authority {
+            return Err(IcoCustomError::AttestationRequired.into());
+        }
+        if now >= attestation.expiry {
+            return Err(IcoCustomError::AttestationExpired.into());
+        }
+        if self.is_jurisdiction_blocked(attestation.jurisdiction) {
+            return Err(IcoCustomError::JurisdictionBlocked.into());
+        }
+        Ok(())
+    }
+
+    // Lamports per whole token at `now`, the marginal price on a bonding curve
+    pub fn current_price(&self, now: i64) -> Result<u64> {
+        let unit = 10u128.pow(self.decimals as u32);
+        let price = match self.sale_mode {
+            SaleMode::FixedPrice => unit / (self.tokens_per_lamport.max(1) as u128),
+            SaleMode::FairLaunch => unit / (self.fair_launch.tokens_per_lamport.max(1) as u128),
+            SaleMode::DutchAuction => {
+                return Ok(self
+                    .auction
+                    .price_at(now)
+                    .ok_or(IcoCustomError::AuctionNotActive)?)
+            }
+            SaleMode::BondingCurve => {
+                return Ok(self
+                    .bonding_curve
+                    .marginal_price(self.ledger.total_sold, self.decimals)
+                    .ok_or(IcoCustomError::MathOverflow)?)
+            }
+        };
+        Ok(price as u64)
+    }
+
+    // Bonus for a purchase: the first time tier still open plus the largest size tier reached
+    pub fn bonus_bps(&self, lamports: u64, now: i64) -> u16 {
+        let time_bps = self
+            .time_bonuses
+            .iter()
+            .find(|tier| tier.bonus_bps > 0 && now < tier.end_ts)
+            .map_or(0, |tier| tier.bonus_bps);
+        let size_bps = self
+            .size_bonuses
+            .iter()
+            .rev()
+            .find(|tier| tier.bonus_bps > 0 && lamports >= tier.min_lamports)
+            .map_or(0, |tier| tier.bonus_bps);
+        time_bps + size_bps
+    }
+}
+
+// Claimed flags of the allocations under one merkle root, one bit per leaf index
+#[account]
+pub struct ClaimBitmap {
+    pub claim_root: [u8; 32],
+    pub leaf_count: u32,
+    pub total_amount: u64,
+    pub claimed_amount: u64,
+    pub bump: u8,
+    pub claimed: Vec<u8>,
+}
+
+impl ClaimBitmap {
+    pub fn space(leaf_count: u32) -> usize {
+        8 + 32 + 4 + 8 + 8 + 1 + 4 + (leaf_count as usize).div_ceil(8)
+    }
+
+    pub fn is_claimed(&self, index: u32) -> bool {
+        self.claimed[index as usize / 8] & (1 << (index % 8)) != 0
+    }
+
+    pub fn set_claimed(&mut self, index: u32) {
+        self.claimed[index as usize / 8] |= 1 << (index % 8);
+    }
+}
+
+// What wallets and explorers display for the sale, set by set_sale_metadata
+#[account]
+pub struct SaleMetadata {
+    pub sale: Pubkey,
+    pub name: String,
+    pub symbol: String,
+    pub website_uri: String,
+    pub whitepaper_uri: String,
+    pub terms_hash: [u8; 32], // SHA-256 of the legal terms, echoed by buyers in buy_with_sol
+    pub bump: u8,
+}
+
+impl SaleMetadata {
+    pub const SPACE: usize =
+        8 + 32 + 4 + MAX_NAME_LEN + 4 + MAX_SYMBOL_LEN + 2 * (4 + MAX_URI_LEN) + 32 + 1;
+}
+
+// Tokens locked by create_lock, held in the lock vault PDA until unlock_ts
+#[account]
+pub struct TokenLock {
+    pub creator: Pubkey,
+    pub mint: Pubkey,
+    pub beneficiary: Pubkey, // owner of the token account release_lock pays to
+    pub amount: u64,         // locked at creation, the vault may hold more if topped up
+    pub unlock_ts: i64,
+    pub released: bool,
+    pub bump: u8,
+    pub vault_bump: u8,
+}
+
+// Buyer's jurisdiction as attested by the compliance authority
+#[account]
+pub struct ComplianceAttestation {
+    pub buyer: Pubkey,
+    pub authority: Pubkey,
+    pub jurisdiction: u16, // ISO 3166-1 numeric
+    pub expiry: i64,
+    pub bump: u8,
+}
+
+// KYC permit issued off-chain, signed by the KYC authority
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct KycPermit {
+    pub max_allocation: u64, // lamports the buyer may pay in total
+    pub expiry: i64,
+}
+
+impl KycPermit {
+    // Signed message: sale || buyer || max_allocation (le) || expiry (le)
+    pub fn message(&self, sale: &Pubkey, buyer: &Pubkey) -> [u8; KYC_PERMIT_MESSAGE_LEN] {
+        let mut message = [0u8; KYC_PERMIT_MESSAGE_LEN];
+        message[..32].copy_from_slice(sale.as_ref());
+        message[32..64].copy_from_slice(buyer.as_ref());
+        message[64..72].copy_from_slice(&self.max_allocation.to_le_bytes());
+        message[72..].copy_from_slice(&self.expiry.to_le_bytes());
+        message
+    }
+}
+
+// Emitted by distribute_batch for every recipient
+#[event]
+pub struct BatchDistribution {
+    pub recipient: Pubkey,
+    pub token_account: Pubkey,
+    pub amount: u64,
+}
+
+// Returned by quote_buy
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct BuyQuote {
+    pub tokens_amount: u64,
+    pub bonus_tokens: u64,
+    pub price: u64, // lamports per whole token
+}
+
+// Returned by get_sale_status
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct SaleStatus {
+    pub sale_mode: SaleMode,
+    pub is_open: bool,
+    pub current_price: u64, // lamports per whole token
+    pub tokens_per_lamport: u64,
+    pub tokens_balance: u64,
+    pub total_sold: u64,
+    pub lamports_received: u64,
+    pub bonus_tokens_distributed: u64,
+    pub referral_tokens_owed: u64,
+    pub timestamp: i64,
+    pub claimed_allocations: u64,
+}
+
+// Returned by get_lock
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct LockStatus {
+    pub creator: Pubkey,
+    pub mint: Pubkey,
+    pub beneficiary: Pubkey,
+    pub amount: u64,
+    pub vault_balance: u64,
+    pub unlock_ts: i64,
+    pub is_unlocked: bool,
+    pub released: bool,
+    pub timestamp: i64,
+}
+
+// Optional bot protections of buy_with_sol, all disabled when zeroed
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct AntiBot {
+    pub slot_window: u64, // purchases are counted per buyer per window of this many slots
+    pub max_buys_per_window: u16, // 0 disables the rate limit
+    pub one_buy_per_tx: bool, // one top-level buy_with_sol per transaction, no CPI buys
+    pub block_cpi: bool,  // reject calls from programs not in cpi_allowlist
+    pub cpi_allowlist: [Pubkey; MAX_CPI_CALLERS],
+}
+
+impl AntiBot {
+    // Empty allow-list slots hold the default pubkey, which never matches
+    pub fn allows_caller(&self, caller: &Pubkey) -> bool {
+        *caller != Pubkey::default() && self.cpi_allowlist.contains(caller)
+    }
+}
+
+// What finalize_sale does with the tokens left for sale
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum UnsoldPolicy {
+    ReturnToAdmin,
+    Burn,
+    MoveTo,
+}
+
+// Max lamports a buyer staking at least `min_staked` may pay in total
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct StakingTier {
+    pub min_staked: u64,
+    pub max_allocation: u64,
+}
+
+// Bonus paid to purchases made before `end_ts`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct TimeBonus {
+    pub end_ts: i64,
+    pub bonus_bps: u16,
+}
+
+// Bonus paid to purchases of at least `min_lamports`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct SizeBonus {
+    pub min_lamports: u64,
+    pub bonus_bps: u16,
+}
+
+// How referral rewards are paid out
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ReferralReward {
+    Tokens,
+    Lamports,
+}
+
+// Per-referrer totals, also escrows the referrer's unclaimed SOL rewards
+#[account]
+pub struct ReferrerStats {
+    pub referrer: Pubkey,
+    pub total_referrals: u64,
+    pub total_referred_lamports: u64,
+    pub total_reward_tokens: u64,
+    pub total_reward_lamports: u64,
+    pub unclaimed_tokens: u64,
+    pub unclaimed_lamports: u64,
+    pub claimable_ts: i64, // the sell-back windows of referred purchases close
+    pub bump: u8,
+}
+
+// Custom error enum for the ICO program
+#[error_code]
+pub enum IcoCustomError {
+    #[msg("Mathematical overflow during operations.")]
+    MathOverflow,
+
+    #[msg("A buyer cannot refer themselves.")]
+    SelfReferral,
+
+    #[msg("There is nothing to claim.")]
+    NothingToClaim,
+
+    #[msg("The auction is not running.")]
+    AuctionNotActive,
+
+    #[msg("The auction has not ended yet.")]
+    AuctionNotEnded,
+
+    #[msg("The fair launch commit window is not open.")]
+    FairLaunchNotActive,
+
+    #[msg("The fair launch has not been finalized yet.")]
+    FairLaunchNotFinalized,
+
+    #[msg("The sell-back window for this purchase has closed.")]
+    SellBackWindowClosed,
+
+    #[msg("The sell-back window for this purchase is still open.")]
+    SellBackWindowOpen,
+
+    #[msg("A KYC permit is required to buy.")]
+    KycPermitRequired,
+
+    #[msg("The KYC permit signature is invalid.")]
+    KycPermitInvalid,
+
+    #[msg("The KYC permit has expired.")]
+    KycPermitExpired,
+
+    #[msg("The purchase exceeds the KYC permit allocation.")]
+    KycAllocationExceeded,
+
+    #[msg("A valid compliance attestation is required to buy.")]
+    AttestationRequired,
+
+    #[msg("The compliance attestation has expired.")]
+    AttestationExpired,
+
+    #[msg("Purchases from this jurisdiction are not allowed.")]
+    JurisdictionBlocked,
+
+    #[msg("The signer is not authorized.")]
+    Unauthorized,
+
+    #[msg("Recorded balance exceeds the program ATA balance.")]
+    VaultBalanceMismatch,
+
+    #[msg("The sale has ended.")]
+    SaleEnded,
+
+    #[msg("The sale has not ended yet.")]
+    SaleNotEnded,
+
+    #[msg("A stake position of the buyer is required to buy.")]
+    StakePositionRequired,
+
+    #[msg("The staked amount does not reach any tier.")]
+    NoStakingTier,
+
+    #[msg("The purchase exceeds the staking tier allocation.")]
+    StakingAllocationExceeded,
+
+    #[msg("This allocation has already been claimed.")]
+    AlreadyClaimed,
+
+    #[msg("The merkle proof does not match the claim root.")]
+    InvalidMerkleProof,
+
+    #[msg("Liquidity must be seeded before the sale is finalized.")]
+    LiquidityNotSeeded,
+
+    #[msg("The lock has not expired yet.")]
+    LockNotExpired,
+
+    #[msg("Too many purchases in the current slot window.")]
+    PurchaseRateLimited,
+
+    #[msg("Only one purchase is allowed per transaction.")]
+    MultipleBuysInTransaction,
+
+    #[msg("Purchases through this program are not allowed.")]
+    CpiCallerNotAllowed,
+
+    #[msg("The sale has started, its metadata is frozen.")]
+    SaleStarted,
+
+    #[msg("A sale metadata field is too long.")]
+    MetadataTooLong,
+
+    #[msg("The terms hash does not match the sale terms.")]
+    TermsNotAccepted,
+
+    #[msg("The AMM pool already holds liquidity.")]
+    PoolNotEmpty,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Borsh reads all-zero bytes as the zero value of every field
+    fn zeroed<T: AnchorDeserialize>() -> T {
+        T::deserialize(&mut &[0u8; 8192][..]).unwrap()
+    }
+
+    // Account owned by `owner` holding `data`, leaked to outlive the test
+    fn account(owner: Pubkey, data: Vec<u8>) -> &'static AccountInfo<'static> {
+        let key = Box::leak(Box::new(Pubkey::new_unique()));
+        let owner = Box::leak(Box::new(owner));
+        let lamports = Box::leak(Box::new(0u64));
+        let data = Box::leak(data.into_boxed_slice());
+        Box::leak(Box::new(AccountInfo::new(
+            key, false, true, lamports, data, owner, false, 0,
+        )))
+    }
+
+    fn stake_position(owner: Pubkey, amount: u64) -> &'static AccountInfo<'static> {
+        let position = mock_staking::StakePosition {
+            owner,
+            amount,
+            bump: 255,
+        };
+        let mut data = Vec::new();
+        position.try_serialize(&mut data).unwrap();
+        account(mock_staking::ID, data)
+    }
+
+    #[test]
+    fn second_purchase_waits_for_the_sell_back_window() {
+        let window = 3_600;
+        let mut contribution: Contribution = zeroed();
+        assert!(contribution.escrow_to_release(true, window, 100).unwrap());
+
+        // the first purchase is escrowed at t = 100
+        contribution.last_purchase_ts = 100;
+        contribution.sell_back_lamports = 1_000;
+        contribution.sell_back_tokens = 10;
+
+        // a second escrowed purchase inside the window is rejected, the first stays refundable
+        assert_eq!(
+            contribution.escrow_to_release(true, window, 100 + window - 1),
+            Err(IcoCustomError::SellBackWindowOpen.into())
+        );
+        // a purchase that is not escrowed leaves the open window alone
+        assert!(!contribution.escrow_to_release(false, window, 101).unwrap());
+        // once the window closed, the first escrow is released before the second purchase
+        assert!(contribution
+            .escrow_to_release(true, window, 100 + window)
+            .unwrap());
+
+        contribution.last_purchase_ts = i64::MAX;
+        assert_eq!(
+            contribution.sell_back_window_end(window),
+            Err(IcoCustomError::MathOverflow.into())
+        );
+    }
+
+    #[test]
+    fn sale_stays_started_after_a_sell_back() {
+        let mut ico_pda: IcoDataPda = zeroed();
+        ico_pda.ledger.deposit(1_000).unwrap();
+        assert!(!ico_pda.has_started(0));
+
+        // buy_with_sol records the purchase and marks the sale started
+        ico_pda.ledger.record_purchase(100, 0, 0, 500).unwrap();
+        ico_pda.sale_started = true;
+        // the buyer sells everything back, no lamports are left received
+        ico_pda.ledger.record_sell_back(100, 0, 0, 500).unwrap();
+        assert_eq!(ico_pda.ledger.lamports_received, 0);
+        assert!(ico_pda.has_started(0));
+    }
+
+    #[test]
+    fn staked_amount_reads_the_staking_program_layout() {
+        let buyer = Pubkey::new_unique();
+        let position = stake_position(buyer, 42_000);
+        assert_eq!(
+            staked_amount(position, &mock_staking::ID, &buyer),
+            Ok(42_000)
+        );
+        // another staker's position, or one owned by another program, is refused
+        assert_eq!(
+            staked_amount(position, &mock_staking::ID, &Pubkey::new_unique()),
+            Err(IcoCustomError::StakePositionRequired.into())
+        );
+        assert_eq!(
+            staked_amount(position, &Pubkey::new_unique(), &buyer),
+            Err(IcoCustomError::StakePositionRequired.into())
+        );
+    }
+
+    #[test]
+    fn staking_cap_counts_earlier_purchases() {
+        let buyer = Pubkey::new_unique();
+        let mut ico_pda: IcoDataPda = zeroed();
+        ico_pda.staking_program = mock_staking::ID;
+        ico_pda.staking_tiers[0] = StakingTier {
+            min_staked: 100,
+            max_allocation: 1_000,
+        };
+        let mut data = Vec::new();
+        ico_pda.try_serialize(&mut data).unwrap();
+        let ico_pda = Account::<IcoDataPda>::try_from(account(crate::ID, data)).unwrap();
+        let position = UncheckedAccount::try_from(stake_position(buyer, 100));
+
+        let mut contribution: Contribution = zeroed();
+        contribution.lamports_paid = 600;
+        let mut check = |lamports| {
+            check_buyer(
+                &ico_pda,
+                &mut contribution,
+                lamports,
+                0,
+                BuyerChecks {
+                    buyer,
+                    permit: None,
+                    terms_hash: [0; 32],
+                    stake_position: Some(&position),
+                    attestation: None,
+                    instructions_sysvar: None,
+                },
+            )
+        };
+        assert_eq!(check(400), Ok(()));
+        assert_eq!(
+            check(401),
+            Err(IcoCustomError::StakingAllocationExceeded.into())
+        );
+    }
+
+    // Ed25519 program instruction signed by `authority`: header, public key at 16,
+    // signature at 48, message at 112
+    fn ed25519_ix(authority: &Pubkey, message: &[u8]) -> Instruction {
+        let mut data = vec![1, 0];
+        for offset in [
+            48,
+            u16::MAX,
+            16,
+            u16::MAX,
+            112,
+            message.len() as u16,
+            u16::MAX,
+        ] {
+            data.extend_from_slice(&offset.to_le_bytes());
+        }
+        data.extend_from_slice(authority.as_ref());
+        data.extend_from_slice(&[7; 64]);
+        data.extend_from_slice(message);
+        Instruction {
+            program_id: ed25519_program::ID,
+            accounts: vec![],
+            data,
+        }
+    }
+
+    #[test]
+    fn kyc_permit_message_layout() {
+        let (sale, buyer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let permit = KycPermit {
+            max_allocation: 0x0102,
+            expiry: -2,
+        };
+        let message = permit.message(&sale, &buyer);
+        assert_eq!(&message[..32], sale.as_ref());
+        assert_eq!(&message[32..64], buyer.as_ref());
+        assert_eq!(message[64..72], [2, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            message[72..],
+            [0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+    }
+
+    #[test]
+    fn kyc_permit_checks_signer_buyer_expiry_and_offsets() {
+        let (authority, sale, buyer) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let permit = KycPermit {
+            max_allocation: 1_000,
+            expiry: 500,
+        };
+        let check = |ix: &Instruction, buyer: &Pubkey, now| {
+            check_ed25519_permit(ix, &authority, &sale, buyer, &permit, now)
+        };
+        let invalid = Err(IcoCustomError::KycPermitInvalid.into());
+
+        let ix = ed25519_ix(&authority, &permit.message(&sale, &buyer));
+        assert_eq!(check(&ix, &buyer, 499), Ok(()));
+        assert_eq!(
+            check(&ix, &buyer, 500),
+            Err(IcoCustomError::KycPermitExpired.into())
+        );
+        // the permit of another buyer
+        assert_eq!(check(&ix, &Pubkey::new_unique(), 0), invalid);
+        // signed by someone else
+        let ix = ed25519_ix(&Pubkey::new_unique(), &permit.message(&sale, &buyer));
+        assert_eq!(check(&ix, &buyer, 0), invalid);
+
+        let valid = ed25519_ix(&authority, &permit.message(&sale, &buyer));
+        // the message read from another instruction
+        let mut ix = valid.clone();
+        ix.data[14..16].copy_from_slice(&0u16.to_le_bytes());
+        assert_eq!(check(&ix, &buyer, 0), invalid);
+        // a public key offset past the end of the data
+        let mut ix = valid.clone();
+        ix.data[6..8].copy_from_slice(&190u16.to_le_bytes());
+        assert_eq!(check(&ix, &buyer, 0), invalid);
+        // a shorter message
+        let mut ix = valid.clone();
+        ix.data[12..14].copy_from_slice(&64u16.to_le_bytes());
+        assert_eq!(check(&ix, &buyer, 0), invalid);
+        // two signatures
+        let mut ix = valid.clone();
+        ix.data[0] = 2;
+        assert_eq!(check(&ix, &buyer, 0), invalid);
+        // not the Ed25519 program
+        let mut ix = valid;
+        ix.program_id = Pubkey::new_unique();
+        assert_eq!(check(&ix, &buyer, 0), invalid);
+    }
+
+    #[test]
+    fn jurisdiction_blocklist_bits() {
+        let mut ico_pda: IcoDataPda = zeroed();
+        ico_pda
+            .set_jurisdiction_blocklist(&[0, 63, 64, 840, 1023])
+            .unwrap();
+        for code in [0, 63, 64, 840, 1023] {
+            assert!(ico_pda.is_jurisdiction_blocked(code));
+        }
+        for code in [1, 62, 65, 276, 839, 841, 1022] {
+            assert!(!ico_pda.is_jurisdiction_blocked(code));
+        }
+        // past the bitmap: cannot be set and always blocked
+        assert_eq!(
+            ico_pda.set_jurisdiction_blocklist(&[276, 1024]),
+            Err(ProgramError::InvalidArgument.into())
+        );
+        assert!(!ico_pda.is_jurisdiction_blocked(276));
+        assert!(ico_pda.is_jurisdiction_blocked(1024));
+        assert!(ico_pda.is_jurisdiction_blocked(u16::MAX));
+    }
+
+    #[test]
+    fn attestation_checks_authority_expiry_and_jurisdiction() {
+        let mut ico_pda: IcoDataPda = zeroed();
+        ico_pda.compliance_authority = Pubkey::new_unique();
+        ico_pda.set_jurisdiction_blocklist(&[840]).unwrap();
+        let mut attestation: ComplianceAttestation = zeroed();
+        attestation.authority = ico_pda.compliance_authority;
+        attestation.jurisdiction = 276;
+        attestation.expiry = 500;
+
+        assert_eq!(ico_pda.check_attestation(&attestation, 499), Ok(()));
+        assert_eq!(
+            ico_pda.check_attestation(&attestation, 500),
+            Err(IcoCustomError::AttestationExpired.into())
+        );
+        attestation.jurisdiction = 840;
+        assert_eq!(
+            ico_pda.check_attestation(&attestation, 0),
+            Err(IcoCustomError::JurisdictionBlocked.into())
+        );
+        attestation.jurisdiction = 2000;
+        assert_eq!(
+            ico_pda.check_attestation(&attestation, 0),
+            Err(IcoCustomError::JurisdictionBlocked.into())
+        );
+        attestation.authority = Pubkey::new_unique();
+        assert_eq!(
+            ico_pda.check_attestation(&attestation, 0),
+            Err(IcoCustomError::AttestationRequired.into())
+        );
+    }
+
+    #[test]
+    fn rate_limit_counts_buys_per_window() {
+        let anti_bot = AntiBot {
+            slot_window: 10,
+            max_buys_per_window: 2,
+            ..Default::default()
+        };
+        let limited = Err(IcoCustomError::PurchaseRateLimited.into());
+        let mut contribution: Contribution = zeroed();
+
+        // the first buy opens the window at its slot
+        assert_eq!(contribution.count_window_buy(&anti_bot, 100), Ok(()));
+        assert_eq!(contribution.window_start_slot, 100);
+        assert_eq!(contribution.count_window_buy(&anti_bot, 109), Ok(()));
+        assert_eq!(contribution.count_window_buy(&anti_bot, 109), limited);
+        assert_eq!(contribution.window_buys, 2);
+        // the window rolls over after slot_window slots
+        assert_eq!(contribution.count_window_buy(&anti_bot, 110), Ok(()));
+        assert_eq!(contribution.window_start_slot, 110);
+        assert_eq!(contribution.window_buys, 1);
+        // the window end saturates instead of overflowing
+        contribution.window_start_slot = u64::MAX - 1;
+        contribution.window_buys = 2;
+        assert_eq!(
+            contribution.count_window_buy(&anti_bot, u64::MAX - 1),
+            limited
+        );
+    }
+
+    #[test]
+    fn cpi_allowlist_matches_listed_programs_only() {
+        let router = Pubkey::new_unique();
+        let mut anti_bot = AntiBot::default();
+        assert!(!anti_bot.allows_caller(&router));
+        anti_bot.cpi_allowlist[1] = router;
+        assert!(anti_bot.allows_caller(&router));
+        assert!(!anti_bot.allows_caller(&Pubkey::new_unique()));
+        // the empty slots do not let the default pubkey through
+        assert!(!anti_bot.allows_caller(&Pubkey::default()));
+    }
+
+    #[test]
+    fn buy_and_commit_instructions_count_as_buys() {
+        let ix = |program_id, data: &[u8]| Instruction {
+            program_id,
+            accounts: vec![],
+            data: data.to_vec(),
+        };
+        let mut buy = instruction::BuyWithSol::DISCRIMINATOR.to_vec();
+        buy.extend_from_slice(&[0; 16]);
+        assert!(is_buy_instruction(&ix(crate::ID, &buy)));
+        assert!(is_buy_instruction(&ix(
+            crate::ID,
+            &instruction::CommitSol::DISCRIMINATOR
+        )));
+        assert!(!is_buy_instruction(&ix(Pubkey::new_unique(), &buy)));
+        assert!(!is_buy_instruction(&ix(
+            crate::ID,
+            &instruction::SellBack::DISCRIMINATOR
+        )));
+        assert!(!is_buy_instruction(&ix(crate::ID, &[])));
+    }
+
+    #[test]
+    fn receipt_keeps_the_last_purchases_and_gross_totals() {
+        let mut receipt: BuyerReceipt = zeroed();
+        let mut clock = Clock::default();
+        let purchases = RECEIPT_HISTORY_LEN as u64 + 3;
+        for n in 1..=purchases {
+            clock.slot = 1_000 + n;
+            clock.unix_timestamp = n as i64;
+            receipt
+                .record(n * 100, n * 50, PaymentCurrency::Sol, 2, &clock)
+                .unwrap();
+        }
+
+        assert_eq!(receipt.purchase_count, purchases);
+        assert_eq!(receipt.first_purchase_slot, 1_001);
+        assert_eq!(receipt.last_purchase_slot, 1_000 + purchases);
+        let sum = purchases * (purchases + 1) / 2;
+        assert_eq!(receipt.total_tokens, sum * 100);
+        assert_eq!(receipt.total_lamports, sum * 50);
+
+        // the three newest purchases overwrote the three oldest
+        assert_eq!(receipt.next_index, 3);
+        let amounts: Vec<u64> = receipt.history.iter().map(|record| record.amount).collect();
+        assert_eq!(amounts, [900, 1_000, 1_100, 400, 500, 600, 700, 800]);
+        // 50 lamports per 100 base units at 2 decimals
+        assert!(receipt.history.iter().all(|record| record.price == 50));
+        assert_eq!(receipt.history[2].timestamp, 11);
+
+        // nothing delivered records a zero price
+        receipt
+            .record(0, 10, PaymentCurrency::Sol, 2, &clock)
+            .unwrap();
+        assert_eq!(receipt.history[3].price, 0);
+    }
+
+    fn bonus_sale() -> IcoDataPda {
+        let mut ico_pda: IcoDataPda = zeroed();
+        ico_pda.tokens_per_lamport = 2;
+        ico_pda.time_bonuses[0] = TimeBonus {
+            end_ts: 100,
+            bonus_bps: 1_000,
+        };
+        ico_pda.time_bonuses[1] = TimeBonus {
+            end_ts: 200,
+            bonus_bps: 500,
+        };
+        ico_pda.size_bonuses[0] = SizeBonus {
+            min_lamports: 1_000,
+            bonus_bps: 200,
+        };
+        ico_pda.size_bonuses[1] = SizeBonus {
+            min_lamports: 10_000,
+            bonus_bps: 700,
+        };
+        ico_pda
+    }
+
+    #[test]
+    fn bonus_tiers_switch_at_their_boundaries() {
+        let ico_pda = bonus_sale();
+        // the first open time tier applies until its end_ts, exclusive
+        assert_eq!(ico_pda.bonus_bps(0, 99), 1_000);
+        assert_eq!(ico_pda.bonus_bps(0, 100), 500);
+        assert_eq!(ico_pda.bonus_bps(0, 199), 500);
+        assert_eq!(ico_pda.bonus_bps(0, 200), 0);
+        // the largest size tier reached applies from its min_lamports, inclusive
+        assert_eq!(ico_pda.bonus_bps(999, 200), 0);
+        assert_eq!(ico_pda.bonus_bps(1_000, 200), 200);
+        assert_eq!(ico_pda.bonus_bps(9_999, 200), 200);
+        assert_eq!(ico_pda.bonus_bps(10_000, 200), 700);
+        // both add up
+        assert_eq!(ico_pda.bonus_bps(10_000, 99), 1_700);
+        // no tiers, no bonus
+        assert_eq!(zeroed::<IcoDataPda>().bonus_bps(u64::MAX, 0), 0);
+    }
+
+    #[test]
+    fn bonus_tokens_are_counted_apart_from_sold_tokens() {
+        let mut ico_pda = bonus_sale();
+        ico_pda.ledger.deposit(1_000_000).unwrap();
+
+        // 1_000 lamports buy 2_000 tokens, 12% on top at t = 99
+        let quote = ico_pda.quote_buy(1_000, 99).unwrap();
+        assert_eq!((quote.tokens_amount, quote.bonus_tokens), (2_000, 240));
+        ico_pda
+            .ledger
+            .record_purchase(quote.tokens_amount, quote.bonus_tokens, 0, 1_000)
+            .unwrap();
+        // the same purchase after the time tiers only gets the size bonus
+        let quote = ico_pda.quote_buy(1_000, 200).unwrap();
+        assert_eq!((quote.tokens_amount, quote.bonus_tokens), (2_000, 40));
+        ico_pda
+            .ledger
+            .record_purchase(quote.tokens_amount, quote.bonus_tokens, 0, 1_000)
+            .unwrap();
+
+        let ledger = &ico_pda.ledger;
+        assert_eq!(ledger.total_sold, 4_000);
+        assert_eq!(ledger.bonus_tokens_distributed, 280);
+        assert_eq!(ledger.tokens_balance, 1_000_000 - 4_280);
+        assert!(ledger.is_balanced());
+
+        // selling the first purchase back returns its bonus too
+        ico_pda
+            .ledger
+            .record_sell_back(2_000, 240, 0, 1_000)
+            .unwrap();
+        assert_eq!(ico_pda.ledger.bonus_tokens_distributed, 40);
+        assert!(ico_pda.ledger.is_balanced());
+    }
+}
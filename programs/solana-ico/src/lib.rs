@@ -34,6 +34,21 @@ pub mod ico {
         token::transfer(cpi_ctx, ico_amount)?;
         msg!("send {} ICO to program ATA.", ico_amount);
 
+        // fund the treasury PDA with its rent exemption so any buy amount can be collected
+        let rent_exemption = Rent::get()?.minimum_balance(0);
+        let ix = anchor_lang::solana_program::system_instruction::transfer(
+            &ctx.accounts.admin.key(),
+            &ctx.accounts.treasury.key(),
+            rent_exemption,
+        );
+        anchor_lang::solana_program::program::invoke(
+            &ix,
+            &[
+                ctx.accounts.admin.to_account_info(),
+                ctx.accounts.treasury.to_account_info(),
+            ],
+        )?;
+
         // save data in data PDA
         let data = &mut ctx.accounts.data;
         data.sol = sol_price;
@@ -49,9 +64,6 @@ pub mod ico {
     ===========================================================
 */
     pub fn deposit_ico_in_ata(ctx: Context<DepositIcoInATA>, ico_amount: u64) -> ProgramResult {
-        if ctx.accounts.data.admin != *ctx.accounts.admin.key {
-            return Err(ProgramError::IncorrectProgramId);
-        }
         // transfer ICO admin to program ata
         let cpi_ctx = CpiContext::new(
             ctx.accounts.token_program.to_account_info(),
@@ -76,23 +88,92 @@ pub mod ico {
         _ico_ata_for_ico_program_bump: u8,
         sol_amount: u64,
     ) -> Result<()> {
-        // transfer sol from user to admin
+        // transfer sol from user to the sale treasury
         let ix = anchor_lang::solana_program::system_instruction::transfer(
             &ctx.accounts.user.key(),
-            &ctx.accounts.admin.key(),
+            &ctx.accounts.treasury.key(),
             sol_amount,
         );
         anchor_lang::solana_program::program::invoke(
             &ix,
             &[
                 ctx.accounts.user.to_account_info(),
-                ctx.accounts.admin.to_account_info(),
+                ctx.accounts.treasury.to_account_info(),
             ],
         )?;
-        msg!("transfer {} sol to admin.", sol_amount);
+        let data = &mut ctx.accounts.data;
+        data.sol_collected = data
+            .sol_collected
+            .checked_add(sol_amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        msg!("transfer {} sol to treasury.", sol_amount);
 
         // transfer ICO from program to user ATA
-        let ico_amount = sol_amount * ctx.accounts.data.sol;
+        let ico_amount = sol_amount
+            .checked_mul(ctx.accounts.data.sol)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        let ico_mint_address = ctx.accounts.ico_mint.key();
+        let seeds = &[ico_mint_address.as_ref(), &[_ico_ata_for_ico_program_bump]];
+        let signer = [&seeds[..]];
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.ico_ata_for_ico_program.to_account_info(),
+                to: ctx.accounts.ico_ata_for_user.to_account_info(),
+                authority: ctx.accounts.ico_ata_for_ico_program.to_account_info(),
+            },
+            &signer,
+        );
+        token::transfer(cpi_ctx, ico_amount)?;
+        msg!("transfer {} ico to buyer/user.", ico_amount);
+        Ok(())
+    }
+
+    /* 
+    ===========================================================
+        create_wsol_treasury function use CreateWsolTreasury struct
+    ===========================================================
+*/
+    pub fn create_wsol_treasury(_ctx: Context<CreateWsolTreasury>) -> Result<()> {
+        msg!("create wSOL treasury token account.");
+        Ok(())
+    }
+
+    /* 
+    ===========================================================
+        buy_with_wsol function use BuyWithWsol struct
+    ===========================================================
+*/
+    pub fn buy_with_wsol(
+        ctx: Context<BuyWithWsol>,
+        _ico_ata_for_ico_program_bump: u8,
+        wsol_amount: u64,
+    ) -> Result<()> {
+        if wsol_amount == 0 {
+            return Err(ProgramError::InvalidArgument.into());
+        }
+
+        // transfer wSOL from user to the wSOL treasury
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.wsol_ata_for_user.to_account_info(),
+                to: ctx.accounts.wsol_treasury.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        );
+        token::transfer(cpi_ctx, wsol_amount)?;
+        let data = &mut ctx.accounts.data;
+        data.wsol_collected = data
+            .wsol_collected
+            .checked_add(wsol_amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        msg!("transfer {} wsol to treasury.", wsol_amount);
+
+        // transfer ICO from program to user ATA, priced like buy_with_sol
+        let ico_amount = wsol_amount
+            .checked_mul(ctx.accounts.data.sol)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
         let ico_mint_address = ctx.accounts.ico_mint.key();
         let seeds = &[ico_mint_address.as_ref(), &[_ico_ata_for_ico_program_bump]];
         let signer = [&seeds[..]];
@@ -110,6 +191,41 @@ pub mod ico {
         Ok(())
     }
 
+    /* 
+    ===========================================================
+        withdraw_wsol function use WithdrawWsol struct
+    ===========================================================
+*/
+    pub fn withdraw_wsol(ctx: Context<WithdrawWsol>, wsol_amount: u64) -> Result<()> {
+        // transfer wSOL from the treasury to the admin wSOL account, the admin
+        // unwraps by closing that account
+        let data_address = ctx.accounts.data.key();
+        let seeds = &[
+            b"wsol_treasury".as_ref(),
+            data_address.as_ref(),
+            &[ctx.bumps.wsol_treasury],
+        ];
+        let signer = [&seeds[..]];
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token::Transfer {
+                from: ctx.accounts.wsol_treasury.to_account_info(),
+                to: ctx.accounts.wsol_ata_for_admin.to_account_info(),
+                authority: ctx.accounts.wsol_treasury.to_account_info(),
+            },
+            &signer,
+        );
+        token::transfer(cpi_ctx, wsol_amount)?;
+
+        let data = &mut ctx.accounts.data;
+        data.wsol_withdrawn = data
+            .wsol_withdrawn
+            .checked_add(wsol_amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        msg!("withdraw {} wsol from treasury to admin.", wsol_amount);
+        Ok(())
+    }
+
     /* 
     ===========================================================
         buy_with_usdt function use BuyWithUsdt struct
@@ -151,15 +267,59 @@ pub mod ico {
         Ok(())
     }
 
+    /* 
+    ===========================================================
+        withdraw_sol function use WithdrawSol struct
+    ===========================================================
+*/
+    pub fn withdraw_sol(ctx: Context<WithdrawSol>, sol_amount: u64) -> Result<()> {
+        // only collected sol can leave, the rent exemption stays in the treasury
+        let data = &ctx.accounts.data;
+        let available = data
+            .sol_collected
+            .checked_sub(data.sol_withdrawn)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        if sol_amount > available {
+            return Err(ProgramError::InsufficientFunds.into());
+        }
+
+        // transfer sol from the treasury PDA to admin
+        let data_address = data.key();
+        let seeds = &[
+            b"treasury".as_ref(),
+            data_address.as_ref(),
+            &[ctx.bumps.treasury],
+        ];
+        let signer = [&seeds[..]];
+        let ix = anchor_lang::solana_program::system_instruction::transfer(
+            &ctx.accounts.treasury.key(),
+            &ctx.accounts.admin.key(),
+            sol_amount,
+        );
+        anchor_lang::solana_program::program::invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.treasury.to_account_info(),
+                ctx.accounts.admin.to_account_info(),
+            ],
+            &signer,
+        )?;
+
+        let data = &mut ctx.accounts.data;
+        data.sol_withdrawn = data
+            .sol_withdrawn
+            .checked_add(sol_amount)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        msg!("withdraw {} sol from treasury to admin.", sol_amount);
+        Ok(())
+    }
+
     /* 
     ===========================================================
         update_data function use UpdateData struct
     ===========================================================
 */
     pub fn update_data(ctx: Context<UpdateData>, sol_price: u64, usdt_price: u64) -> ProgramResult {
-        if ctx.accounts.data.admin != *ctx.accounts.admin.key {
-            return Err(ProgramError::IncorrectProgramId);
-        }
         let data = &mut ctx.accounts.data;
         data.sol = sol_price;
         data.usdt = usdt_price;
@@ -191,12 +351,20 @@ pub mod ico {
         #[account(init, payer=admin, space=9000, seeds=[b"data", admin.key().as_ref()], bump)]
         pub data: Account<'info, Data>,
 
+        // system owned PDA that collects the SOL paid by buyers
+        #[account(mut, seeds = [b"treasury", data.key().as_ref()], bump)]
+        pub treasury: SystemAccount<'info>,
+
         #[account(
         address = ICO_MINT_ADDRESS.parse::<Pubkey>().unwrap(),
     )]
         pub ico_mint: Account<'info, Mint>,
 
-        #[account(mut)]
+        #[account(
+        mut,
+        token::mint = ico_mint,
+        token::authority = admin,
+    )]
         pub ico_ata_for_admin: Account<'info, TokenAccount>,
 
         #[account(mut)]
@@ -214,10 +382,19 @@ pub mod ico {
 */
     #[derive(Accounts)]
     pub struct DepositIcoInATA<'info> {
-        #[account(mut)]
+        #[account(
+        mut,
+        seeds = [ ico_mint.key().as_ref() ],
+        bump,
+    )]
         pub ico_ata_for_ico_program: Account<'info, TokenAccount>,
 
-        #[account(mut)]
+        #[account(
+        mut,
+        seeds = [b"data", admin.key().as_ref()],
+        bump,
+        has_one = admin,
+    )]
         pub data: Account<'info, Data>,
 
         #[account(
@@ -225,7 +402,11 @@ pub mod ico {
     )]
         pub ico_mint: Account<'info, Mint>,
 
-        #[account(mut)]
+        #[account(
+        mut,
+        token::mint = ico_mint,
+        token::authority = admin,
+    )]
         pub ico_ata_for_admin: Account<'info, TokenAccount>,
 
         #[account(mut)]
@@ -248,28 +429,164 @@ pub mod ico {
     )]
         pub ico_ata_for_ico_program: Account<'info, TokenAccount>,
 
-        #[account(mut)]
+        #[account(
+        mut,
+        seeds = [b"data", admin.key().as_ref()],
+        bump,
+        has_one = admin,
+    )]
         pub data: Account<'info, Data>,
 
+        #[account(mut, seeds = [b"treasury", data.key().as_ref()], bump)]
+        pub treasury: SystemAccount<'info>,
+
         #[account(
         address = ICO_MINT_ADDRESS.parse::<Pubkey>().unwrap(),
     )]
         pub ico_mint: Account<'info, Mint>,
 
-        #[account(mut)]
+        #[account(
+        mut,
+        token::mint = ico_mint,
+        token::authority = user,
+    )]
         pub ico_ata_for_user: Account<'info, TokenAccount>,
 
         #[account(mut)]
         pub user: Signer<'info>,
 
-        /// CHECK:
-        #[account(mut)]
-        pub admin: AccountInfo<'info>,
+        pub admin: SystemAccount<'info>,
 
         pub token_program: Program<'info, Token>,
         pub system_program: Program<'info, System>,
     }
 
+    /* 
+    -----------------------------------------------------------
+        CreateWsolTreasury struct for create_wsol_treasury function
+    -----------------------------------------------------------
+*/
+    #[derive(Accounts)]
+    pub struct CreateWsolTreasury<'info> {
+        // wSOL token account that holds wSOL proceeds, its own authority like the ICO ATA
+        #[account(
+        init,
+        payer = admin,
+        seeds = [b"wsol_treasury", data.key().as_ref()],
+        bump,
+        token::mint = wsol_mint,
+        token::authority = wsol_treasury,
+    )]
+        pub wsol_treasury: Account<'info, TokenAccount>,
+
+        #[account(
+        seeds = [b"data", admin.key().as_ref()],
+        bump,
+        has_one = admin,
+    )]
+        pub data: Account<'info, Data>,
+
+        #[account(
+        address = token::spl_token::native_mint::ID,
+    )]
+        pub wsol_mint: Account<'info, Mint>,
+
+        #[account(mut)]
+        pub admin: Signer<'info>,
+
+        pub system_program: Program<'info, System>,
+        pub token_program: Program<'info, Token>,
+        pub rent: Sysvar<'info, Rent>,
+    }
+
+    /* 
+    -----------------------------------------------------------
+        BuyWithWsol struct for buy_with_wsol function
+    -----------------------------------------------------------
+*/
+    #[derive(Accounts)]
+    #[instruction(_ico_ata_for_ico_program_bump: u8)]
+    pub struct BuyWithWsol<'info> {
+        #[account(
+        mut,
+        seeds = [ ico_mint.key().as_ref() ],
+        bump = _ico_ata_for_ico_program_bump,
+    )]
+        pub ico_ata_for_ico_program: Account<'info, TokenAccount>,
+
+        #[account(
+        mut,
+        seeds = [b"data", data.admin.as_ref()],
+        bump,
+    )]
+        pub data: Account<'info, Data>,
+
+        #[account(
+        mut,
+        seeds = [b"wsol_treasury", data.key().as_ref()],
+        bump,
+    )]
+        pub wsol_treasury: Account<'info, TokenAccount>,
+
+        #[account(
+        address = ICO_MINT_ADDRESS.parse::<Pubkey>().unwrap(),
+    )]
+        pub ico_mint: Account<'info, Mint>,
+
+        #[account(
+        mut,
+        token::mint = ico_mint,
+        token::authority = user,
+    )]
+        pub ico_ata_for_user: Account<'info, TokenAccount>,
+
+        #[account(
+        mut,
+        token::mint = token::spl_token::native_mint::ID,
+        token::authority = user,
+    )]
+        pub wsol_ata_for_user: Account<'info, TokenAccount>,
+
+        #[account(mut)]
+        pub user: Signer<'info>,
+
+        pub token_program: Program<'info, Token>,
+    }
+
+    /* 
+    -----------------------------------------------------------
+        WithdrawWsol struct for withdraw_wsol function
+    -----------------------------------------------------------
+*/
+    #[derive(Accounts)]
+    pub struct WithdrawWsol<'info> {
+        #[account(
+        mut,
+        seeds = [b"data", admin.key().as_ref()],
+        bump,
+        has_one = admin,
+    )]
+        pub data: Account<'info, Data>,
+
+        #[account(
+        mut,
+        seeds = [b"wsol_treasury", data.key().as_ref()],
+        bump,
+    )]
+        pub wsol_treasury: Account<'info, TokenAccount>,
+
+        #[account(
+        mut,
+        token::mint = token::spl_token::native_mint::ID,
+        token::authority = admin,
+    )]
+        pub wsol_ata_for_admin: Account<'info, TokenAccount>,
+
+        #[account(mut)]
+        pub admin: Signer<'info>,
+        pub token_program: Program<'info, Token>,
+    }
+
     /* 
     -----------------------------------------------------------
         BuyWithUsdt struct for buy_with_usdt function
@@ -285,7 +602,10 @@ pub mod ico {
     )]
         pub ico_ata_for_ico_program: Account<'info, TokenAccount>,
 
-        #[account(mut)]
+        #[account(
+        seeds = [b"data", data.admin.as_ref()],
+        bump,
+    )]
         pub data: Account<'info, Data>,
 
         #[account(
@@ -293,13 +613,25 @@ pub mod ico {
     )]
         pub ico_mint: Account<'info, Mint>,
 
-        #[account(mut)]
+        #[account(
+        mut,
+        token::mint = ico_mint,
+        token::authority = user,
+    )]
         pub ico_ata_for_user: Account<'info, TokenAccount>,
 
-        #[account(mut)]
+        #[account(
+        mut,
+        token::mint = USDT_MINT_ADDRESS.parse::<Pubkey>().unwrap(),
+        token::authority = user,
+    )]
         pub usdt_ata_for_user: Account<'info, TokenAccount>,
 
-        #[account(mut)]
+        #[account(
+        mut,
+        token::mint = USDT_MINT_ADDRESS.parse::<Pubkey>().unwrap(),
+        token::authority = data.admin,
+    )]
         pub usdt_ata_for_admin: Account<'info, TokenAccount>,
 
         #[account(mut)]
@@ -308,6 +640,29 @@ pub mod ico {
         pub token_program: Program<'info, Token>,
     }
 
+    /* 
+    -----------------------------------------------------------
+        WithdrawSol struct for withdraw_sol function
+    -----------------------------------------------------------
+*/
+    #[derive(Accounts)]
+    pub struct WithdrawSol<'info> {
+        #[account(
+        mut,
+        seeds = [b"data", admin.key().as_ref()],
+        bump,
+        has_one = admin,
+    )]
+        pub data: Account<'info, Data>,
+
+        #[account(mut, seeds = [b"treasury", data.key().as_ref()], bump)]
+        pub treasury: SystemAccount<'info>,
+
+        #[account(mut)]
+        pub admin: Signer<'info>,
+        pub system_program: Program<'info, System>,
+    }
+
     /* 
     -----------------------------------------------------------
         UpdateData struct for update_data function
@@ -315,7 +670,12 @@ pub mod ico {
 */
     #[derive(Accounts)]
     pub struct UpdateData<'info> {
-        #[account(mut)]
+        #[account(
+        mut,
+        seeds = [b"data", admin.key().as_ref()],
+        bump,
+        has_one = admin,
+    )]
         pub data: Account<'info, Data>,
         #[account(mut)]
         pub admin: Signer<'info>,
@@ -332,5 +692,9 @@ pub mod ico {
         pub sol: u64,
         pub usdt: u64,
         pub admin: Pubkey,
+        pub sol_collected: u64,
+        pub sol_withdrawn: u64,
+        pub wsol_collected: u64,
+        pub wsol_withdrawn: u64,
     }
 }
@@ -1,5 +1,6 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::system_program;
 use anchor_spl::token::{self, Mint, Token, TokenAccount};
 
 declare_id!("FZZPymCYLZHYb3krdyXSPLvm2YqNmJTZLrjenaCJNJGE");
@@ -39,6 +40,17 @@ pub mod ico {
         token::transfer(cpi_ctx, ico_tokens_amount)?;
         msg!("send {} ICO token to program ATA.", ico_tokens_amount);
 
+        // fund the treasury with its rent exemption so small purchases and
+        // withdrawals never leave it below the rent minimum
+        let cpi_ctx = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.admin.to_account_info(),
+                to: ctx.accounts.treasury.to_account_info(),
+            },
+        );
+        system_program::transfer(cpi_ctx, Rent::get()?.minimum_balance(0))?;
+
         // save ico_data in ico_data PDA
         ico_data.sol_per_token = sol_per_token;
         ico_data.admin = *ctx.accounts.admin.key;
@@ -56,11 +68,7 @@ pub mod ico {
     pub fn deposit_ico_tokens_in_ata(
         ctx: Context<DepositIcoTokensInATA>,
         ico_tokens_amount: u64,
-    ) -> ProgramResult {
-        if ctx.accounts.ico_data.admin != *ctx.accounts.admin.key {
-            msg!("incorrect authority/admin.");
-            return Err(ProgramError::IllegalOwner);
-        }
+    ) -> Result<()> {
         // transfer ICO tokens from admin ata to program ata
         let cpi_ctx = CpiContext::new(
             ctx.accounts.token_program.to_account_info(),
@@ -73,7 +81,10 @@ pub mod ico {
         token::transfer(cpi_ctx, ico_tokens_amount)?;
 
         let ico_data = &mut ctx.accounts.ico_data;
-        ico_data.ico_tokens_balance += ico_tokens_amount;
+        ico_data.ico_tokens_balance = ico_data
+            .ico_tokens_balance
+            .checked_add(ico_tokens_amount)
+            .ok_or(IcoCustomError::MathOverflow)?;
         msg!(
             "deposit {} more ICO tokens in program ATA.",
             ico_tokens_amount
@@ -83,29 +94,98 @@ pub mod ico {
 
     /* 
     ===========================================================
-        withdraw_ico_tokens_from_ata function use WithdrawIcoTokensFromATA struct
+        withdraw_tokens function use WithdrawTokens struct
     ===========================================================
 */
-    pub fn withdraw_ico_tokens_from_ata(ctx: Context<WithdrawIcoTokensFromATA>) -> ProgramResult {
-        if ctx.accounts.ico_data.admin != *ctx.accounts.admin.key {
-            msg!("incorrect authority/admin.");
-            return Err(ProgramError::IllegalOwner);
+    pub fn withdraw_tokens(ctx: Context<WithdrawTokens>, ico_tokens_amount: u64) -> Result<()> {
+        let ico_data = &mut ctx.accounts.ico_data;
+        if ico_tokens_amount == 0 {
+            return Err(IcoCustomError::InvalidAmount.into());
         }
-        // transfer ICO tokens from program ata to admin ata
-        let cpi_ctx = CpiContext::new(
+        if ico_data.ico_tokens_balance < ico_tokens_amount {
+            msg!("program ata don't have enough tokens.");
+            return Err(IcoCustomError::InsufficientFundsInIco.into());
+        }
+
+        // transfer ICO tokens from program ata to admin ata,
+        // the program ata is its own authority so the PDA signs
+        let ico_mint_address = ctx.accounts.ico_mint.key();
+        let seeds = &[
+            ico_mint_address.as_ref(),
+            &[ctx.bumps.ico_ata_for_ico_program],
+        ];
+        let signer = [&seeds[..]];
+        let cpi_ctx = CpiContext::new_with_signer(
             ctx.accounts.token_program.to_account_info(),
             token::Transfer {
                 from: ctx.accounts.ico_ata_for_ico_program.to_account_info(),
                 to: ctx.accounts.ico_ata_for_admin.to_account_info(),
-                authority: ctx.accounts.admin.to_account_info(),
+                authority: ctx.accounts.ico_ata_for_ico_program.to_account_info(),
             },
+            &signer,
         );
+        token::transfer(cpi_ctx, ico_tokens_amount)?;
 
         // update pda data
+        ico_data.ico_tokens_balance = ico_data
+            .ico_tokens_balance
+            .checked_sub(ico_tokens_amount)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        ico_data.total_ico_tokens_withdrawn = ico_data
+            .total_ico_tokens_withdrawn
+            .checked_add(ico_tokens_amount)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        msg!(
+            "withdraw {} ICO tokens from program ATA.",
+            ico_tokens_amount
+        );
+        Ok(())
+    }
+
+    /* 
+    ===========================================================
+        withdraw_proceeds function use WithdrawProceeds struct
+    ===========================================================
+*/
+    pub fn withdraw_proceeds(
+        ctx: Context<WithdrawProceeds>,
+        sol_amount_in_lamport: u64,
+    ) -> Result<()> {
         let ico_data = &mut ctx.accounts.ico_data;
-        token::transfer(cpi_ctx, ico_data.ico_tokens_balance)?;
-        ico_data.ico_tokens_balance = 0;
-        msg!("withdraw ICO from program ATA.");
+        if sol_amount_in_lamport == 0 {
+            return Err(IcoCustomError::InvalidAmount.into());
+        }
+        let available = ico_data
+            .total_sol_recived
+            .checked_sub(ico_data.total_sol_withdrawn)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        if available < sol_amount_in_lamport {
+            msg!("treasury don't have enough sol.");
+            return Err(IcoCustomError::InsufficientFundsInTreasury.into());
+        }
+
+        // transfer sol (lamports) from treasury PDA to admin
+        let seeds = &[b"treasury".as_ref(), &[ctx.bumps.treasury]];
+        let signer = [&seeds[..]];
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.system_program.to_account_info(),
+            system_program::Transfer {
+                from: ctx.accounts.treasury.to_account_info(),
+                to: ctx.accounts.admin.to_account_info(),
+            },
+            &signer,
+        );
+        system_program::transfer(cpi_ctx, sol_amount_in_lamport)?;
+
+        // update pda data
+        ico_data.total_sol_withdrawn = ico_data
+            .total_sol_withdrawn
+            .checked_add(sol_amount_in_lamport)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        msg!(
+            "withdraw {} sol (lamports) from treasury.",
+            sol_amount_in_lamport
+        );
         Ok(())
     }
 
@@ -126,23 +206,25 @@ pub mod ico {
             return Err(IcoCustomError::InsufficientFundsInIco.into());
         }
 
-        let sol_amount_in_lamport = ico_tokens_amount * ico_data.sol_per_token;
+        let sol_amount_in_lamport = ico_tokens_amount
+            .checked_mul(ico_data.sol_per_token)
+            .ok_or(IcoCustomError::MathOverflow)?;
 
-        // transfer sol from user to admin
+        // transfer sol from user to treasury
         let ix = anchor_lang::solana_program::system_instruction::transfer(
             &ctx.accounts.user.key(),
-            &ctx.accounts.admin.key(),
+            &ctx.accounts.treasury.key(),
             sol_amount_in_lamport,
         );
         anchor_lang::solana_program::program::invoke(
             &ix,
             &[
                 ctx.accounts.user.to_account_info(),
-                ctx.accounts.admin.to_account_info(),
+                ctx.accounts.treasury.to_account_info(),
             ],
         )?;
         msg!(
-            "transfer {} sol (lamports) to admin.",
+            "transfer {} sol (lamports) to treasury.",
             sol_amount_in_lamport
         );
 
@@ -162,9 +244,18 @@ pub mod ico {
 
         token::transfer(cpi_ctx, ico_tokens_amount)?;
         // update ico data
-        ico_data.total_sol_recived += sol_amount_in_lamport;
-        ico_data.total_ico_tokens_sold += ico_tokens_amount;
-        ico_data.ico_tokens_balance -= ico_tokens_amount;
+        ico_data.total_sol_recived = ico_data
+            .total_sol_recived
+            .checked_add(sol_amount_in_lamport)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        ico_data.total_ico_tokens_sold = ico_data
+            .total_ico_tokens_sold
+            .checked_add(ico_tokens_amount)
+            .ok_or(IcoCustomError::MathOverflow)?;
+        ico_data.ico_tokens_balance = ico_data
+            .ico_tokens_balance
+            .checked_sub(ico_tokens_amount)
+            .ok_or(IcoCustomError::MathOverflow)?;
         msg!("transfer {} ico tokens to buyer/user.", ico_tokens_amount);
         Ok(())
     }
@@ -178,10 +269,6 @@ pub mod ico {
         ctx: Context<UpdateIcoTokenPrice>,
         sol_per_token: u64,
     ) -> ProgramResult {
-        if ctx.accounts.ico_data.admin != *ctx.accounts.admin.key {
-            msg!("incorrect authority/admin.");
-            return Err(ProgramError::IllegalOwner);
-        }
         let ico_data = &mut ctx.accounts.ico_data;
         ico_data.sol_per_token = sol_per_token;
         msg!("update SOL/ICO_Token: {} ", sol_per_token);
@@ -217,12 +304,20 @@ pub mod ico {
         )]
         pub ico_mint: Account<'info, Mint>,
 
-        #[account(mut)]
+        #[account(
+        mut,
+        token::mint = ico_mint,
+        token::authority = admin,
+        )]
         pub ico_ata_for_admin: Account<'info, TokenAccount>,
 
         #[account(mut)]
         pub admin: Signer<'info>,
 
+        // program treasury that holds the sol paid by buyers
+        #[account(mut, seeds = [b"treasury"], bump)]
+        pub treasury: SystemAccount<'info>,
+
         pub system_program: Program<'info, System>,
         pub token_program: Program<'info, Token>,
         pub rent: Sysvar<'info, Rent>,
@@ -235,10 +330,14 @@ pub mod ico {
 */
     #[derive(Accounts)]
     pub struct DepositIcoTokensInATA<'info> {
-        #[account(mut)]
+        #[account(
+        mut,
+        seeds = [ ico_mint.key().as_ref() ],
+        bump,
+        )]
         pub ico_ata_for_ico_program: Account<'info, TokenAccount>,
 
-        #[account(mut)]
+        #[account(mut, seeds = [b"ico_data"], bump, has_one = admin)]
         pub ico_data: Account<'info, IcoData>,
 
         #[account(
@@ -246,7 +345,11 @@ pub mod ico {
         )]
         pub ico_mint: Account<'info, Mint>,
 
-        #[account(mut)]
+        #[account(
+        mut,
+        token::mint = ico_mint,
+        token::authority = admin,
+        )]
         pub ico_ata_for_admin: Account<'info, TokenAccount>,
 
         #[account(mut)]
@@ -256,15 +359,19 @@ pub mod ico {
 
     /* 
     -----------------------------------------------------------
-        WithdrawIcoTokensFromATA struct for withdraw_ico_tokens_from_ata function
+        WithdrawTokens struct for withdraw_tokens function
     -----------------------------------------------------------
 */
     #[derive(Accounts)]
-    pub struct WithdrawIcoTokensFromATA<'info> {
-        #[account(mut)]
+    pub struct WithdrawTokens<'info> {
+        #[account(
+        mut,
+        seeds = [ ico_mint.key().as_ref() ],
+        bump,
+        )]
         pub ico_ata_for_ico_program: Account<'info, TokenAccount>,
 
-        #[account(mut)]
+        #[account(mut, seeds = [b"ico_data"], bump, has_one = admin)]
         pub ico_data: Account<'info, IcoData>,
 
         #[account(
@@ -272,7 +379,11 @@ pub mod ico {
         )]
         pub ico_mint: Account<'info, Mint>,
 
-        #[account(mut)]
+        #[account(
+        mut,
+        token::mint = ico_mint,
+        token::authority = admin,
+        )]
         pub ico_ata_for_admin: Account<'info, TokenAccount>,
 
         #[account(mut)]
@@ -280,6 +391,24 @@ pub mod ico {
         pub token_program: Program<'info, Token>,
     }
 
+    /* 
+    -----------------------------------------------------------
+        WithdrawProceeds struct for withdraw_proceeds function
+    -----------------------------------------------------------
+*/
+    #[derive(Accounts)]
+    pub struct WithdrawProceeds<'info> {
+        #[account(mut, seeds = [b"treasury"], bump)]
+        pub treasury: SystemAccount<'info>,
+
+        #[account(mut, seeds = [b"ico_data"], bump, has_one = admin)]
+        pub ico_data: Account<'info, IcoData>,
+
+        #[account(mut)]
+        pub admin: Signer<'info>,
+        pub system_program: Program<'info, System>,
+    }
+
     /* 
     -----------------------------------------------------------
         BuyWithSol struct for buy_with_sol function
@@ -295,7 +424,7 @@ pub mod ico {
         )]
         pub ico_ata_for_ico_program: Account<'info, TokenAccount>,
 
-        #[account(mut)]
+        #[account(mut, seeds = [b"ico_data"], bump, has_one = admin)]
         pub ico_data: Account<'info, IcoData>,
 
         #[account(
@@ -303,15 +432,21 @@ pub mod ico {
         )]
         pub ico_mint: Account<'info, Mint>,
 
-        #[account(mut)]
+        #[account(
+        mut,
+        token::mint = ico_mint,
+        token::authority = user,
+        )]
         pub ico_ata_for_user: Account<'info, TokenAccount>,
 
         #[account(mut)]
         pub user: Signer<'info>,
 
-        /// CHECK:
-        #[account(mut)]
-        pub admin: AccountInfo<'info>,
+        pub admin: SystemAccount<'info>,
+
+        // program treasury that holds the sol paid by buyers
+        #[account(mut, seeds = [b"treasury"], bump)]
+        pub treasury: SystemAccount<'info>,
 
         pub token_program: Program<'info, Token>,
         pub system_program: Program<'info, System>,
@@ -324,7 +459,7 @@ pub mod ico {
 */
     #[derive(Accounts)]
     pub struct UpdateIcoTokenPrice<'info> {
-        #[account(mut)]
+        #[account(mut, seeds = [b"ico_data"], bump, has_one = admin)]
         pub ico_data: Account<'info, IcoData>,
         #[account(mut)]
         pub admin: Signer<'info>,
@@ -345,6 +480,8 @@ pub mod ico {
         pub ico_tokens_balance: u64,
         pub admin: Pubkey,
         pub initiate: bool,
+        pub total_sol_withdrawn: u64,
+        pub total_ico_tokens_withdrawn: u64,
     }
 
     /* 
@@ -360,5 +497,11 @@ pub enum IcoCustomError {
     AccountAlreadyInitialized,
     #[msg("Insufficient funds in ICO.")]
     InsufficientFundsInIco,
+    #[msg("Insufficient funds in treasury.")]
+    InsufficientFundsInTreasury,
+    #[msg("Amount must be greater than zero.")]
+    InvalidAmount,
+    #[msg("Arithmetic overflow.")]
+    MathOverflow,
 }
 
@@ -15,10 +15,6 @@ mod ico_program {
         tokens_per_lamport: u64,
         tokens_deposit_for_ico: u64,
     ) -> Result<()> {
-        // Check for same mint
-        if ctx.accounts.ico_mint.key() != ICO_MINT.parse::<Pubkey>().unwrap() {
-            return Err(IcoCustomError::InvalidMint.into());
-        }
         // Check for invalid SOL per token value
         if tokens_per_lamport == 0 {
             return Err(IcoCustomError::InvalidAmount.into());
@@ -60,11 +56,6 @@ mod ico_program {
         }
 
         let ico_pda = &mut ctx.accounts.ico_pda;
-        // Ensure the admin address is correct
-        if ctx.accounts.admin.key() != ico_pda.admin {
-            // return Err(IcoCustomError::Unauthorized.into());
-            return Err(ProgramError::InvalidArgument.into());
-        }
 
         // Calculate total cost and check for overflow
         let tokens_amount = lamports
@@ -139,10 +130,6 @@ mod ico_program {
         }
 
         let ico_pda = &ctx.accounts.ico_pda;
-        // Verify that only the admin can perform this action
-        if ctx.accounts.admin.key() != ico_pda.admin {
-            return Err(IcoCustomError::Unauthorized.into());
-        }
         // Ensure enough tokens are available for withdrawal
         if ico_pda.tokens_balance < amount {
             return Err(IcoCustomError::NotEnoughTokensAvailable.into());
@@ -181,12 +168,6 @@ mod ico_program {
             return Err(IcoCustomError::InvalidAmount.into());
         }
 
-        let ico_pda = &ctx.accounts.ico_pda;
-        // Verify that only the admin can perform this action
-        if ctx.accounts.admin.key() != ico_pda.admin {
-            return Err(IcoCustomError::Unauthorized.into());
-        }
-
         // transfer tokens from admin ata to program ata
         let cpi_ctx = CpiContext::new(
             ctx.accounts.token_program.to_account_info(),
@@ -215,12 +196,6 @@ mod ico_program {
             return Err(IcoCustomError::InvalidAmount.into());
         }
 
-        let ico_pda = &ctx.accounts.ico_pda;
-        // Verify that only the admin can perform this action
-        if ctx.accounts.admin.key() != ico_pda.admin {
-            return Err(IcoCustomError::Unauthorized.into());
-        }
-
         // Update data for the withdrawn amount
         let ico_pda = &mut ctx.accounts.ico_pda;
         ico_pda.tokens_per_lamport = tokens_per_lamport;
@@ -237,9 +212,15 @@ mod ico_program {
 pub struct InitiateAndCreateProgramATA<'info> {
     #[account(mut)]
     pub admin: Signer<'info>,
+
+    #[account(address = ICO_MINT.parse::<Pubkey>().unwrap() @ IcoCustomError::InvalidMint)]
     pub ico_mint: Account<'info, Mint>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        token::mint = ico_mint,
+        token::authority = admin,
+    )]
     pub admin_ata: Account<'info, TokenAccount>,
 
     #[account(
@@ -271,11 +252,14 @@ pub struct BuyWithSol<'info> {
     #[account(mut)]
     pub buyer: Signer<'info>,
 
-    /// CHECK:
     #[account(mut)]
-    pub admin: AccountInfo<'info>,
+    pub admin: SystemAccount<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        token::mint = ICO_MINT.parse::<Pubkey>().unwrap(),
+        token::authority = buyer,
+    )]
     pub buyer_ata: Account<'info, TokenAccount>,
 
     #[account(
@@ -285,7 +269,7 @@ pub struct BuyWithSol<'info> {
         )]
     pub program_ata: Account<'info, TokenAccount>,
 
-    #[account(mut)]
+    #[account(mut, has_one = admin @ IcoCustomError::Unauthorized, seeds = [b"ico_pda"], bump)]
     pub ico_pda: Account<'info, IcoDataPda>,
 
     pub token_program: Program<'info, Token>,
@@ -298,7 +282,11 @@ pub struct WithdrawTokens<'info> {
     #[account(mut)]
     pub admin: Signer<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        token::mint = ICO_MINT.parse::<Pubkey>().unwrap(),
+        token::authority = admin,
+    )]
     pub admin_ata: Account<'info, TokenAccount>,
 
     // #[account(
@@ -314,7 +302,7 @@ pub struct WithdrawTokens<'info> {
         )]
     pub program_ata: Account<'info, TokenAccount>,
 
-    #[account(mut)]
+    #[account(mut, has_one = admin @ IcoCustomError::Unauthorized, seeds = [b"ico_pda"], bump)]
     pub ico_pda: Account<'info, IcoDataPda>,
 
     pub token_program: Program<'info, Token>,
@@ -326,13 +314,21 @@ pub struct DepositTokens<'info> {
     #[account(mut)]
     pub admin: Signer<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        token::mint = ICO_MINT.parse::<Pubkey>().unwrap(),
+        token::authority = admin,
+    )]
     pub admin_ata: Account<'info, TokenAccount>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        seeds = [ ICO_MINT.parse::<Pubkey>().unwrap().as_ref() ],
+        bump,
+        )]
     pub program_ata: Account<'info, TokenAccount>,
 
-    #[account(mut)]
+    #[account(mut, has_one = admin @ IcoCustomError::Unauthorized, seeds = [b"ico_pda"], bump)]
     pub ico_pda: Account<'info, IcoDataPda>,
 
     pub token_program: Program<'info, Token>,
@@ -351,7 +347,7 @@ pub struct UpdatePrice<'info> {
         )]
     pub program_ata: Account<'info, TokenAccount>,
 
-    #[account(mut)]
+    #[account(mut, has_one = admin @ IcoCustomError::Unauthorized, seeds = [b"ico_pda"], bump)]
     pub ico_pda: Account<'info, IcoDataPda>,
 
     pub system_program: Program<'info, System>,
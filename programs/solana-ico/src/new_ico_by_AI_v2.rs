@@ -223,7 +223,11 @@ pub struct InitiateAndCreateProgramATA<'info> {
     #[account(address = ICO_MINT.parse::<Pubkey>().unwrap())]
     pub ico_mint: Account<'info, Mint>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        token::mint = ico_mint,
+        token::authority = admin,
+    )]
     pub admin_ata: Account<'info, TokenAccount>,
 
     #[account(
@@ -256,9 +260,13 @@ pub struct BuyWithSol<'info> {
     pub buyer: Signer<'info>,
 
     #[account(mut)]
-    pub admin: AccountInfo<'info>,
+    pub admin: SystemAccount<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        token::mint = ICO_MINT.parse::<Pubkey>().unwrap(),
+        token::authority = buyer,
+    )]
     pub buyer_ata: Account<'info, TokenAccount>,
 
     #[account(
@@ -280,7 +288,11 @@ pub struct WithdrawTokens<'info> {
     #[account(mut)]
     pub admin: Signer<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        token::mint = ICO_MINT.parse::<Pubkey>().unwrap(),
+        token::authority = admin,
+    )]
     pub admin_ata: Account<'info, TokenAccount>,
 
     #[account(
@@ -301,7 +313,11 @@ pub struct WithdrawTokens<'info> {
 pub struct DepositTokens<'info> {
     pub admin: Signer<'info>,
 
-    #[account(mut)]
+    #[account(
+        mut,
+        token::mint = ICO_MINT.parse::<Pubkey>().unwrap(),
+        token::authority = admin,
+    )]
     pub admin_ata: Account<'info, TokenAccount>,
 
     #[account(
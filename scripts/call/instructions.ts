@@ -9,7 +9,12 @@ import {
   web3 as anchorWeb3,
 } from "@project-serum/anchor";
 import { getNetworkConfig } from "../splHelper/helper";
-import { getOrCreateAssociatedTokenAccount, TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import {
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  getAssociatedTokenAddressSync,
+  getOrCreateAssociatedTokenAccount,
+  TOKEN_PROGRAM_ID,
+} from "@solana/spl-token";
 import { findProgramAddressSync } from "@project-serum/anchor/dist/cjs/utils/pubkey";
 
 const idl = require("./idl.json");
@@ -98,29 +103,53 @@ async function initiateAndCreateProgramATA(tokensPerLamport, tokensDepositForICO
   console.log(`sigTx: `, sigTx);
 }
 
-async function buyWithSol(amount, buyerPublicKey = undefined) {
-  const { adminWallet, connection, program, icoMint, programATA, icoPDA } =
+// termsHash: SHA-256 of the sale terms accepted by the buyer, zeros when the sale has none
+async function buyWithSol(
+  amount,
+  buyerPublicKey = undefined,
+  referrer = undefined,
+  termsHash = new Array(32).fill(0)
+) {
+  const { adminWallet, program, prodramId, icoMint, programATA, icoPDA } =
     getProviderAndAddresses();
 
   buyerPublicKey = buyerPublicKey ?? adminWallet.publicKey;
 
-  const buyerAta = await getOrCreateAssociatedTokenAccount(
-    connection,
-    adminWallet,
-    icoMint,
-    buyerPublicKey
+  // the program creates the buyer ATA on the first purchase
+  const buyerAta = getAssociatedTokenAddressSync(icoMint, buyerPublicKey);
+
+  const [contribution] = findProgramAddressSync(
+    [Buffer.from("contribution"), buyerPublicKey.toBuffer()],
+    prodramId
+  );
+
+  const [receipt] = findProgramAddressSync(
+    [Buffer.from("receipt"), icoPDA.toBuffer(), buyerPublicKey.toBuffer()],
+    prodramId
   );
 
+  const referrerStats = referrer
+    ? findProgramAddressSync([Buffer.from("referrer"), referrer.toBuffer()], prodramId)[0]
+    : null;
+
   const sigTx = await program.methods
-    .buyWithSol(new BN(amount))
+    .buyWithSol(new BN(amount), null, termsHash)
     .accounts({
       admin: adminWallet.publicKey,
+      icoMint,
       buyer: buyerPublicKey,
-      buyerAta: buyerAta.address,
+      buyerAta,
       programAta: programATA,
       icoPda: icoPDA,
+      contribution,
+      receipt,
+      referrerStats,
+      stakePosition: null,
+      attestation: null,
+      instructionsSysvar: null,
       systemProgram: anchorWeb3.SystemProgram.programId,
       tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
     })
     .signers([adminWallet])
     .rpc();